@@ -0,0 +1,221 @@
+//! Alternate signal stack (sigaltstack) management.
+//!
+//! A handler for a stack overflow SIGSEGV can't run on the stack that just
+//! overflowed. It needs an alternate signal stack, which is per thread. This
+//! module sets them up and tears them down.
+//!
+//! Once any alternate stack has been installed through this module, every
+//! handler Addy installs gets `SA_ONSTACK` so it runs on the alternate stack
+//! of whichever thread the signal lands on.
+//!
+//! # Example
+//! ```
+//! fn main() -> std::io::Result<()> {
+//!     /* The main thread */
+//!     let _stack = addy::altstack::install_for_current_thread(addy::altstack::DEFAULT_SIZE)?;
+//!
+//!     /* A worker thread */
+//!     let worker = addy::altstack::spawn(
+//!         std::thread::Builder::new().name("worker".into()),
+//!         addy::altstack::DEFAULT_SIZE,
+//!         || { /* Do work */ },
+//!     )?;
+//!     worker.join().unwrap();
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Catching a stack overflow
+//! ```
+//! use std::io::Read;
+//! use std::fs::File;
+//! use std::os::unix::io::FromRawFd;
+//!
+//! fn recurse(depth: u64) -> u64 {
+//!     let frame = [depth; 64];
+//!     if std::hint::black_box(depth) == u64::MAX {
+//!         return 0;
+//!     }
+//!     recurse(depth + 1) + std::hint::black_box(&frame)[0]
+//! }
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut fds = [0; 2];
+//!     unsafe { libc::pipe(fds.as_mut_ptr()) };
+//!
+//!     /* Overflow the stack of a thread in a child process */
+//!     match unsafe { libc::fork() } {
+//!         0 => {
+//!             addy::crash::install(addy::crash::Config::new().fd(fds[1])).unwrap();
+//!             let overflow = addy::altstack::spawn(
+//!                 std::thread::Builder::new().stack_size(256 * 1024),
+//!                 addy::altstack::DEFAULT_SIZE,
+//!                 || recurse(0),
+//!             ).unwrap();
+//!             let _ = overflow.join();
+//!             unsafe { libc::_exit(0) };
+//!         }
+//!         child => {
+//!             unsafe { libc::close(fds[1]) };
+//!             let mut report = String::new();
+//!             unsafe { File::from_raw_fd(fds[0]) }.read_to_string(&mut report)?;
+//!
+//!             let mut status = 0;
+//!             unsafe { libc::waitpid(child, &mut status, 0) };
+//!             assert!(libc::WIFSIGNALED(status));
+//!             assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+//!             assert!(report.contains("fatal signal SIGSEGV"));
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::io;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// A reasonable alternate stack size for handlers that don't do much,
+/// 64KiB.
+pub const DEFAULT_SIZE: usize = 64 * 1024;
+
+/* Flipped the first time an alternate stack is installed */
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once an alternate stack has been installed through this
+/// module, i.e. Addy's handlers are being installed with `SA_ONSTACK`.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/* Turns on SA_ONSTACK, including for handlers that are already installed */
+fn enable() {
+    if !ENABLED.swap(true, Ordering::Relaxed) {
+        crate::add_onstack_to_handlers();
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/* Returns the calling thread's current alternate stack */
+fn current() -> io::Result<libc::stack_t> {
+    unsafe {
+        let mut stack = std::mem::zeroed::<libc::stack_t>();
+        match libc::sigaltstack(std::ptr::null(), &mut stack) {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(stack),
+        }
+    }
+}
+
+/*************
+ * ALT STACK *
+ *************/
+
+/// An alternate signal stack installed on the current thread. Dropping it
+/// puts back whatever alternate stack the thread had before and frees the
+/// memory. It has to be dropped on the thread that created it.
+#[derive(Debug)]
+pub struct AltStack {
+    /* Start of the mapping, including the guard page */
+    map: *mut libc::c_void,
+    map_size: usize,
+    previous: libc::stack_t,
+    /* sigaltstack is per thread, so the guard is too */
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for AltStack {
+    fn drop(&mut self) {
+        unsafe {
+            /* Can't pull the stack out from under a running handler */
+            if let Ok(stack) = current() {
+                if stack.ss_flags & libc::SS_ONSTACK != 0 {
+                    return;
+                }
+            }
+            libc::sigaltstack(&self.previous, std::ptr::null_mut());
+            libc::munmap(self.map, self.map_size);
+        }
+    }
+}
+
+/// Installs an alternate signal stack of `size` bytes on the calling thread
+/// and turns on `SA_ONSTACK` for Addy's handlers. The stack gets a guard
+/// page so a handler that overflows it faults instead of corrupting memory.
+pub fn install_for_current_thread(size: usize) -> io::Result<AltStack> {
+    let page = page_size();
+    let size = size.max(libc::SIGSTKSZ);
+    /* Round up to whole pages plus the guard page */
+    let map_size = size.div_ceil(page) * page + page;
+
+    unsafe {
+        let map = libc::mmap(
+            std::ptr::null_mut(),
+            map_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        /* Stacks grow down, so the guard goes at the bottom */
+        if libc::mprotect(map, page, libc::PROT_NONE) == -1 {
+            let err = io::Error::last_os_error();
+            libc::munmap(map, map_size);
+            return Err(err);
+        }
+
+        let stack = libc::stack_t {
+            ss_sp: (map as *mut u8).add(page) as *mut libc::c_void,
+            ss_flags: 0,
+            ss_size: map_size - page,
+        };
+        let mut previous = std::mem::zeroed::<libc::stack_t>();
+        if libc::sigaltstack(&stack, &mut previous) == -1 {
+            let err = io::Error::last_os_error();
+            libc::munmap(map, map_size);
+            return Err(err);
+        }
+
+        enable();
+
+        Ok(AltStack {
+            map,
+            map_size,
+            previous,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+/// Makes sure the calling thread has an alternate stack, keeping the one it
+/// has if any (Rust's runtime installs one on most threads). Otherwise one
+/// is installed that lives as long as the thread.
+pub fn ensure_for_current_thread(size: usize) -> io::Result<()> {
+    if current()?.ss_flags & libc::SS_DISABLE == 0 {
+        enable();
+        return Ok(());
+    }
+    std::mem::forget(install_for_current_thread(size)?);
+    Ok(())
+}
+
+/// Spawns a thread with `builder` that installs an alternate stack of
+/// `size` bytes before running `f`.
+pub fn spawn<F, T>(builder: thread::Builder, size: usize, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    builder.spawn(move || {
+        /* Running without one beats not running at all */
+        let _stack = install_for_current_thread(size).ok();
+        f()
+    })
+}
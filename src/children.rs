@@ -0,0 +1,431 @@
+//! A SIGCHLD child reaper with per-child notifications.
+//!
+//! A SIGCHLD only tells you that *some* child changed state. Once installed,
+//! Addy runs a `waitpid(WNOHANG)` loop on every SIGCHLD and routes each exit,
+//! stop and continue to the callbacks or channels registered for that pid.
+//!
+//! # Interoperating with std::process::Child
+//! With `Reap::All` (the default) every child is reaped, including ones you
+//! never registered, so there are never zombies. That also means
+//! `std::process::Child::wait()` will fail since the child is already gone -
+//! use `children::on_exit(...)` instead and convert the `ExitStatus` with
+//! `.into()` if you need a `std::process::ExitStatus`.
+//!
+//! With `Reap::Registered` only the pids you registered are waited on and
+//! everyone else's children are left for `Child::wait()`.
+//!
+//! # Example
+//! ```
+//! use std::process::Command;
+//! use std::sync::mpsc;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     addy::children::install(addy::children::Reap::All)?;
+//!
+//!     let child = Command::new("sh").args(&["-c", "exit 3"]).spawn()?;
+//!     let (tx, rx) = mpsc::channel();
+//!     addy::children::on_exit(child.id() as libc::pid_t, move |status| {
+//!         let _ = tx.send(status);
+//!     });
+//!
+//!     let status = rx.recv()?;
+//!     assert_eq!(status.code(), Some(3));
+//!     Ok(())
+//! }
+//! ```
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::os::unix::process::ExitStatusExt;
+use std::sync::{mpsc, Arc, Mutex};
+
+use fnv::FnvHashMap;
+use lazy_static::lazy_static;
+
+use crate::{Error, Signal, SIGCHLD};
+
+/* Name our callback is registered under on SIGCHLD */
+const CALLBACK_NAME: &str = "addy::children";
+
+/* How many exits of children we hold on to. Lets on_exit(...) registered
+ * after the child already died, or while its exit was being handed out,
+ * still fire.
+*/
+const MAX_UNCLAIMED: usize = 256;
+
+/***************
+ * EXIT STATUS *
+ ***************/
+
+/// How a child terminated, decoded from the raw `waitpid` status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExitStatus(libc::c_int);
+
+impl ExitStatus {
+    /// The exit code if the child exited normally.
+    pub fn code(self) -> Option<i32> {
+        if libc::WIFEXITED(self.0) {
+            Some(libc::WEXITSTATUS(self.0))
+        } else {
+            None
+        }
+    }
+
+    /// The signal that terminated the child, if a signal did. None for
+    /// signals Addy doesn't know about (e.g. real-time signals), use
+    /// `.signal_number()` for those.
+    pub fn signal(self) -> Option<Signal> {
+        self.signal_number()
+            .and_then(|signum| Signal::try_from(signum).ok())
+    }
+
+    /// The raw number of the signal that terminated the child.
+    pub fn signal_number(self) -> Option<libc::c_int> {
+        if libc::WIFSIGNALED(self.0) {
+            Some(libc::WTERMSIG(self.0))
+        } else {
+            None
+        }
+    }
+
+    /// True if the child was terminated by a signal and dumped core.
+    pub fn core_dumped(self) -> bool {
+        libc::WIFSIGNALED(self.0) && libc::WCOREDUMP(self.0)
+    }
+
+    /// True if the child exited with code 0.
+    pub fn success(self) -> bool {
+        self.code() == Some(0)
+    }
+
+    /// The undecoded status as returned by `waitpid`.
+    pub fn raw(self) -> libc::c_int {
+        self.0
+    }
+}
+
+impl From<ExitStatus> for std::process::ExitStatus {
+    fn from(status: ExitStatus) -> Self {
+        std::process::ExitStatus::from_raw(status.0)
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.code(), self.signal_number()) {
+            (Some(code), _) => write!(f, "exit code: {}", code),
+            (_, Some(signum)) => {
+                match Signal::try_from(signum) {
+                    Ok(signal) => write!(f, "signal: {}", signal)?,
+                    Err(signum) => write!(f, "signal: {}", signum)?,
+                }
+                if self.core_dumped() {
+                    f.write_str(" (core dumped)")?;
+                }
+                Ok(())
+            }
+            _ => write!(f, "unrecognised wait status: {}", self.0),
+        }
+    }
+}
+
+/***************
+ * CHILD EVENT *
+ ***************/
+
+/// A state change of a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChildEvent {
+    /// The child terminated. This is always the last event for a pid.
+    Exited(ExitStatus),
+    /// The child was stopped by the given signal number.
+    Stopped(libc::c_int),
+    /// The child was resumed by SIGCONT.
+    Continued,
+}
+
+impl ChildEvent {
+    fn decode(status: libc::c_int) -> Option<ChildEvent> {
+        if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+            Some(ChildEvent::Exited(ExitStatus(status)))
+        } else if libc::WIFSTOPPED(status) {
+            Some(ChildEvent::Stopped(libc::WSTOPSIG(status)))
+        } else if libc::WIFCONTINUED(status) {
+            Some(ChildEvent::Continued)
+        } else {
+            None
+        }
+    }
+}
+
+/**********
+ * REAPER *
+ **********/
+
+/// Which children the reaper waits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reap {
+    /// Wait on every child, even ones nobody registered, so none are left
+    /// as zombies.
+    All,
+    /// Only wait on pids with a registered callback or channel. Other
+    /// children are left for `std::process::Child::wait()`.
+    Registered,
+}
+
+/* Shared so dispatch(...) can run them with the lock released while they
+ * stay registered
+*/
+type Listener = Arc<Mutex<dyn FnMut(ChildEvent) + Send>>;
+
+struct Reaper {
+    /* Nothing is waited on before install(...), the pids may belong to a
+     * std::process::Child
+    	*/
+    installed: bool,
+    policy: Reap,
+    listeners: FnvHashMap<libc::pid_t, Vec<Listener>>,
+    unclaimed: VecDeque<(libc::pid_t, ExitStatus)>,
+}
+
+lazy_static! {
+    static ref REAPER: Mutex<Reaper> = Mutex::new(Reaper {
+        installed: false,
+        policy: Reap::All,
+        listeners: FnvHashMap::default(),
+        unclaimed: VecDeque::new(),
+    });
+}
+
+thread_local! {
+    /* State changes waiting to be dispatched while this thread is running
+     * listeners. A listener that calls back in and reaps its own pid's exit
+     * would otherwise wait on itself.
+        */
+    static PENDING: RefCell<Option<VecDeque<(libc::pid_t, libc::c_int)>>> = const { RefCell::new(None) };
+}
+
+/* Stops queueing once the outermost reap(...) is done, even if a listener
+ * panicked
+*/
+struct Draining;
+
+impl Drop for Draining {
+    fn drop(&mut self) {
+        PENDING.with(|pending| *pending.borrow_mut() = None);
+    }
+}
+
+/* Non-blocking wait for pid (-1 for any child) */
+fn wait(pid: libc::pid_t) -> Option<(libc::pid_t, libc::c_int)> {
+    let mut status = 0;
+    let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+    match unsafe { libc::waitpid(pid, &mut status, flags) } {
+        pid if pid > 0 => Some((pid, status)),
+        _ => None,
+    }
+}
+
+/* True if pid is still a child of ours, running or not yet reaped. Checked
+ * with WNOWAIT so nothing is taken away from the reaper.
+*/
+fn is_child(pid: libc::pid_t) -> bool {
+    let mut info = std::mem::MaybeUninit::<libc::siginfo_t>::zeroed();
+    let flags = libc::WEXITED | libc::WSTOPPED | libc::WCONTINUED | libc::WNOHANG | libc::WNOWAIT;
+    unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, info.as_mut_ptr(), flags) == 0 }
+}
+
+/* Collects every pending state change, then hands them out with the lock
+ * released so listeners can call back into this module.
+*/
+fn reap() {
+    let mut events = Vec::new();
+    {
+        let reaper = REAPER.lock().unwrap();
+        if !reaper.installed {
+            return;
+        }
+        match reaper.policy {
+            Reap::All => {
+                while let Some(event) = wait(-1) {
+                    events.push(event);
+                }
+            }
+            Reap::Registered => {
+                for pid in reaper.listeners.keys() {
+                    while let Some(event) = wait(*pid) {
+                        events.push(event);
+                    }
+                }
+            }
+        }
+    }
+
+    /* Already handing events out further up, they go to the back of its queue */
+    let nested = PENDING.with(|pending| match pending.borrow_mut().as_mut() {
+        Some(pending) => {
+            pending.extend(events.drain(..));
+            true
+        }
+        None => false,
+    });
+    if nested {
+        return;
+    }
+
+    PENDING.with(|pending| *pending.borrow_mut() = Some(events.into()));
+    let _draining = Draining;
+    while let Some((pid, status)) =
+        PENDING.with(|pending| pending.borrow_mut().as_mut()?.pop_front())
+    {
+        if let Some(event) = ChildEvent::decode(status) {
+            dispatch(pid, event);
+        }
+    }
+}
+
+/* Runs a listener, one that panicked before is still usable */
+fn call(listener: &Listener, event: ChildEvent) {
+    (listener.lock().unwrap_or_else(|err| err.into_inner()))(event);
+}
+
+fn dispatch(pid: libc::pid_t, event: ChildEvent) {
+    let listeners = {
+        let mut reaper = REAPER.lock().unwrap();
+        match event {
+            /* The pid is gone, it can be reused, so are its listeners. The
+             * exit is kept for a late on_exit, including one registered by a
+             * listener that's running right now.
+            	*/
+            ChildEvent::Exited(status) => {
+                /* Only the latest exit of a pid counts, the others were
+                 * children the pid belonged to before
+                	*/
+                reaper.unclaimed.retain(|(p, _)| *p != pid);
+                if reaper.unclaimed.len() == MAX_UNCLAIMED {
+                    reaper.unclaimed.pop_front();
+                }
+                reaper.unclaimed.push_back((pid, status));
+                reaper.listeners.remove(&pid).unwrap_or_default()
+            }
+            /* They stay registered so an exit dispatched meanwhile finds them */
+            _ => match reaper.listeners.get(&pid) {
+                Some(listeners) => listeners.clone(),
+                None => return,
+            },
+        }
+    };
+
+    for listener in listeners.iter() {
+        call(listener, event);
+    }
+}
+
+/* Adds a listener, or runs it straight away if the child already exited.
+ * Checked under one lock so an exit can't slip in between. If pid is a child
+ * again the exit we kept was for an earlier child that had the same pid.
+*/
+fn listen(pid: libc::pid_t, listener: Listener) {
+    let exited = {
+        let mut reaper = REAPER.lock().unwrap();
+        if is_child(pid) {
+            reaper.unclaimed.retain(|(p, _)| *p != pid);
+        }
+        let index = reaper.unclaimed.iter().position(|(p, _)| *p == pid);
+        match index.and_then(|index| reaper.unclaimed.remove(index)) {
+            Some((_, status)) => Some((listener, status)),
+            None => {
+                reaper.listeners.entry(pid).or_default().push(listener);
+                None
+            }
+        }
+    };
+
+    match exited {
+        Some((listener, status)) => call(&listener, ChildEvent::Exited(status)),
+        /* With Reap::Registered nobody waited on this pid until now. Does
+         * nothing before install(...)
+        	*/
+        None => reap(),
+    }
+}
+
+/**********
+ * PUBLIC *
+ **********/
+
+/// Installs the SIGCHLD reaper. Safe to call more than once, the latest
+/// policy wins. Children that changed state before installing are picked
+/// up straight away.
+pub fn install(policy: Reap) -> Result<(), Error> {
+    {
+        let mut reaper = REAPER.lock().unwrap();
+        reaper.installed = true;
+        reaper.policy = policy;
+    }
+    crate::mediate(SIGCHLD)
+        .register(CALLBACK_NAME, |_signal| reap())?
+        .enable()?;
+    /* Behind the .enable() so a SIGCHLD from after this reap can't arrive
+     * before the handler is installed and be lost
+    	*/
+    crate::run_on_loop(reap)?;
+    Ok(())
+}
+
+/// Stops reaping children. Registered callbacks and channels are dropped.
+pub fn uninstall() -> Result<(), Error> {
+    crate::mediate(SIGCHLD).remove(CALLBACK_NAME)?;
+    let mut reaper = REAPER.lock().unwrap();
+    reaper.installed = false;
+    reaper.listeners.clear();
+    reaper.unclaimed.clear();
+    Ok(())
+}
+
+/// Calls `callback` once with the exit status of `pid`. If the child has
+/// already been reaped the callback runs immediately. Nothing is reaped
+/// until `install(...)` has been called.
+pub fn on_exit<F>(pid: libc::pid_t, callback: F)
+where
+    F: FnOnce(ExitStatus) + Send + 'static,
+{
+    let mut callback = Some(callback);
+    listen(
+        pid,
+        Arc::new(Mutex::new(move |event| {
+            if let ChildEvent::Exited(status) = event {
+                if let Some(callback) = callback.take() {
+                    callback(status);
+                }
+            }
+        })),
+    );
+}
+
+/// Calls `callback` for every exit, stop and continue of `pid`. The
+/// callback is dropped after the child exits.
+pub fn watch<F>(pid: libc::pid_t, callback: F)
+where
+    F: FnMut(ChildEvent) + Send + 'static,
+{
+    listen(pid, Arc::new(Mutex::new(callback)));
+}
+
+/// Returns a channel that receives every exit, stop and continue of `pid`.
+/// The sender is dropped after the child exits.
+pub fn subscribe(pid: libc::pid_t) -> mpsc::Receiver<ChildEvent> {
+    let (sender, receiver) = mpsc::channel();
+    watch(pid, move |event| {
+        let _ = sender.send(event);
+    });
+    receiver
+}
+
+/// Same as `on_exit(child.id(), callback)` for a `std::process::Child`.
+pub fn on_child_exit<F>(child: &std::process::Child, callback: F)
+where
+    F: FnOnce(ExitStatus) + Send + 'static,
+{
+    on_exit(child.id() as libc::pid_t, callback);
+}
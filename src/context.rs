@@ -0,0 +1,155 @@
+/* Letting a callback manage its own signal.
+ *
+ * A callback that calls addy::mediate(...) sends to the channel of the Event
+ * Loop it is running on, so the change only lands after everything already
+ * queued. A CallbackContext collects the changes instead and the Event Loop
+ * applies them as soon as the callbacks for the current event are done,
+ * before it looks at its channel again.
+*/
+use std::cell::{Cell, RefCell};
+
+use crate::{observe, Action, Name, Registration, Signal, SignalEvent, CBP};
+
+/* How many triggers deep callbacks can go before the Event Loop stops
+ * running them. Callbacks that trigger each other would never end.
+*/
+pub(crate) const MAX_TRIGGER_DEPTH: usize = 16;
+
+thread_local! {
+    /* Changes the callbacks that just ran asked for, on the Event Loop */
+    static DEFERRED: RefCell<Vec<Action>> = const { RefCell::new(Vec::new()) };
+
+    /* Set while the Event Loop on this thread is running callbacks */
+    static DISPATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Handed to callbacks registered with `.register_context()`. Changes made
+/// through it take effect once the callbacks for the current signal have
+/// run, before the Event Loop handles anything else.
+#[derive(Debug)]
+pub struct CallbackContext {
+    event: SignalEvent,
+    name: Name,
+    actions: Vec<Action>,
+}
+
+impl CallbackContext {
+    pub(crate) fn new(event: SignalEvent, name: Name) -> Self {
+        CallbackContext {
+            event,
+            name,
+            actions: Vec::new(),
+        }
+    }
+
+    /// The signal the callback is running for.
+    pub fn signal(&self) -> Signal {
+        self.event.signal()
+    }
+
+    /// The delivery the callback is running for.
+    pub fn event(&self) -> &SignalEvent {
+        &self.event
+    }
+
+    /// The name the callback was registered with, without its namespace.
+    pub fn name(&self) -> &str {
+        &self.name.name
+    }
+
+    /// Removes the running callback, like `SignalHandle::remove(...)` with
+    /// its own name.
+    pub fn remove_self(&mut self) -> &mut Self {
+        let remove = Action::Remove(self.signal(), self.name.clone());
+        self.actions.push(remove);
+        self
+    }
+
+    /// Has the process ignore the signal, like `SignalHandle::ignore()`.
+    pub fn ignore(&mut self) -> &mut Self {
+        self.actions.push(Action::Ignore(self.signal()));
+        self
+    }
+
+    /// Puts the signal's default behaviour back, like
+    /// `SignalHandle::default()`.
+    pub fn default(&mut self) -> &mut Self {
+        self.actions.push(Action::Default(self.signal()));
+        self
+    }
+
+    /// Registers another callback for the signal, like
+    /// `SignalHandle::register(...)`, in the running callback's namespace.
+    /// It runs from the next delivery on.
+    pub fn register<A, F>(&mut self, name: A, cb: F) -> &mut Self
+    where
+        A: AsRef<str>,
+        F: Fn(Signal) + Send + 'static,
+    {
+        let name = Name {
+            namespace: self.name.namespace.clone(),
+            name: String::from(name.as_ref()),
+        };
+        let cb = observe(
+            self.signal(),
+            &name.to_string(),
+            move |event: &SignalEvent| cb(event.signal()),
+        );
+        let registration = Registration {
+            signal: self.signal(),
+            name,
+            tags: Vec::new(),
+            cb: CBP(Box::new(cb)),
+            added: None,
+        };
+        self.actions.push(Action::Register(registration));
+        self
+    }
+
+    /// Runs the callbacks for `signal` as if it had just arrived, without
+    /// going through the kernel. Only callbacks of a signal this Event Loop
+    /// is handling run.
+    ///
+    /// Callbacks run this way can trigger further signals, up to 16 triggers
+    /// deep. Past that the trigger is dropped and reported to
+    /// `addy::on_error(...)`, so callbacks that trigger each other can't
+    /// hang the Event Loop.
+    pub fn trigger<S: Into<Signal>>(&mut self, signal: S) -> &mut Self {
+        self.actions
+            .push(Action::Call(SignalEvent::new(signal.into())));
+        self
+    }
+
+    /* Leaves the changes for the Event Loop to pick up */
+    pub(crate) fn defer(self) {
+        DEFERRED.with(|deferred| deferred.borrow_mut().extend(self.actions));
+    }
+}
+
+/* The changes callbacks asked for since the last call */
+pub(crate) fn take() -> Vec<Action> {
+    DEFERRED.with(|deferred| std::mem::take(&mut *deferred.borrow_mut()))
+}
+
+/* Marks the thread as running callbacks for as long as it's held, so calls
+ * that wait for the Event Loop can refuse instead of waiting on themselves.
+*/
+pub(crate) struct Dispatching(bool);
+
+impl Dispatching {
+    pub(crate) fn enter() -> Self {
+        Dispatching(DISPATCHING.with(|dispatching| dispatching.replace(true)))
+    }
+}
+
+impl Drop for Dispatching {
+    fn drop(&mut self) {
+        let previous = self.0;
+        DISPATCHING.with(|dispatching| dispatching.set(previous));
+    }
+}
+
+/* True if called from a callback the Event Loop is running */
+pub(crate) fn dispatching() -> bool {
+    DISPATCHING.with(Cell::get)
+}
@@ -0,0 +1,334 @@
+//! An in-handler crash reporter for SIGSEGV, SIGBUS, SIGFPE, SIGILL and
+//! SIGABRT.
+//!
+//! These signals can't be deferred to the Event Loop like everything else.
+//! The faulting thread keeps re-faulting, or the process is gone before a
+//! callback gets to run. `crash::install(...)` bypasses the Event Loop and
+//! installs a strictly async-signal-safe handler which runs on an alternate
+//! stack (so stack overflows are reported too). It writes the signal, fault
+//! address, thread id and a raw backtrace to a pre-opened fd, then restores
+//! `SIG_DFL` and re-raises the signal so core dumps and exit statuses stay
+//! correct. The fault address is only written for a SIGSEGV, SIGBUS, SIGFPE
+//! or SIGILL the kernel raised, not for SIGABRT or one sent with kill(2).
+//!
+//! Nothing in the handler allocates or takes a lock. The backtrace is a list
+//! of return addresses, feed them to `addr2line` or `atos` to symbolise them.
+//!
+//! # Example
+//! ```no_run
+//! use std::fs::File;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let log = File::create("/var/log/myapp/crash.log")?;
+//!     addy::crash::install(addy::crash::Config::new().file(log))?;
+//!
+//!     /* Run the application */
+//!     Ok(())
+//! }
+//! ```
+//!
+//! A crash looks like this:
+//! ```text
+//! addy: fatal signal SIGSEGV (11) at address 0x0 in thread 48213
+//! addy: backtrace:
+//!   #0 0x55d0c1a8e2f3
+//!   #1 0x55d0c1a8e5a1
+//!   ...
+//! ```
+use std::convert::TryFrom;
+use std::fs::File;
+use std::os::unix::io::{IntoRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use crate::{Error, Signal, SIGABRT, SIGBUS, SIGFPE, SIGILL, SIGSEGV};
+
+/* The handler can only read plain atomics */
+static FD: AtomicI32 = AtomicI32::new(libc::STDERR_FILENO);
+static BACKTRACE: AtomicBool = AtomicBool::new(true);
+
+/* Most frames we'll report */
+const MAX_FRAMES: usize = 64;
+
+/**********
+ * CONFIG *
+ **********/
+
+/// Configures the crash reporter. Defaults to reporting SIGSEGV, SIGBUS,
+/// SIGFPE, SIGILL and SIGABRT to stderr with a backtrace, using a 64KiB
+/// alternate stack.
+#[derive(Debug, Clone)]
+pub struct Config {
+    fd: RawFd,
+    signals: Vec<Signal>,
+    backtrace: bool,
+    alt_stack_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            fd: libc::STDERR_FILENO,
+            signals: vec![SIGSEGV, SIGBUS, SIGFPE, SIGILL, SIGABRT],
+            backtrace: true,
+            alt_stack_size: crate::altstack::DEFAULT_SIZE,
+        }
+    }
+}
+
+impl Config {
+    /// Same as `Config::default()`.
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    /// Writes reports to `fd`. It must stay open for the life of the process.
+    pub fn fd(mut self, fd: RawFd) -> Self {
+        self.fd = fd;
+        self
+    }
+
+    /// Writes reports to `file`. The file is kept open for the life of the
+    /// process.
+    pub fn file(mut self, file: File) -> Self {
+        self.fd = file.into_raw_fd();
+        self
+    }
+
+    /// Replaces the signals that are reported.
+    pub fn signals(mut self, signals: &[Signal]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Whether to include a raw backtrace (default true). Only available on
+    /// glibc and macOS, ignored elsewhere.
+    pub fn backtrace(mut self, backtrace: bool) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// Size of the alternate stack the handler runs on.
+    pub fn alt_stack_size(mut self, size: usize) -> Self {
+        self.alt_stack_size = size;
+        self
+    }
+}
+
+/**********
+ * REPORT *
+ **********/
+
+/* A fixed size, stack allocated line buffer. Formatting with std::fmt isn't
+ * async-signal-safe, so we do it by hand.
+*/
+struct Report {
+    fd: RawFd,
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Report {
+    fn new(fd: RawFd) -> Self {
+        Report {
+            fd,
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+
+    fn str(&mut self, s: &str) -> &mut Self {
+        for byte in s.bytes() {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+        self
+    }
+
+    fn digits(&mut self, mut n: u64, base: u64) -> &mut Self {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut tmp = [0u8; 20];
+        let mut i = tmp.len();
+        loop {
+            i -= 1;
+            tmp[i] = DIGITS[(n % base) as usize];
+            n /= base;
+            if n == 0 {
+                break;
+            }
+        }
+        /* Only ASCII digits went in */
+        self.str(std::str::from_utf8(&tmp[i..]).unwrap_or("?"))
+    }
+
+    fn dec(&mut self, n: u64) -> &mut Self {
+        self.digits(n, 10)
+    }
+
+    fn hex(&mut self, n: usize) -> &mut Self {
+        self.str("0x").digits(n as u64, 16)
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let ret = unsafe {
+                libc::write(
+                    self.fd,
+                    self.buf[written..self.len].as_ptr() as *const libc::c_void,
+                    self.len - written,
+                )
+            };
+            if ret <= 0 {
+                break;
+            }
+            written += ret as usize;
+        }
+        self.len = 0;
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn thread_id() -> u64 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u64 }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn thread_id() -> u64 {
+    unsafe { libc::pthread_self() as u64 }
+}
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn backtrace(frames: &mut [*mut libc::c_void; MAX_FRAMES]) -> usize {
+    let count = unsafe { libc::backtrace(frames.as_mut_ptr(), MAX_FRAMES as libc::c_int) };
+    count.max(0) as usize
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn backtrace(_frames: &mut [*mut libc::c_void; MAX_FRAMES]) -> usize {
+    0
+}
+
+/***********
+ * HANDLER *
+ ***********/
+
+/* Everything in here must be async-signal-safe */
+extern "C" fn crash_handler(signum: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let mut report = Report::new(FD.load(Ordering::Relaxed));
+
+    report.str("addy: fatal signal ");
+    match Signal::try_from(signum) {
+        Ok(signal) => report.str(signal.as_str()),
+        Err(_) => report.str("UNKNOWN"),
+    };
+    report.str(" (").dec(signum as u64).str(")");
+    /* si_addr is only the faulting address for faults the kernel raised
+     * (si_code > 0), kill(2) and friends leave it meaningless
+    	*/
+    let fault = matches!(
+        signum,
+        libc::SIGSEGV | libc::SIGBUS | libc::SIGFPE | libc::SIGILL
+    );
+    if fault && !info.is_null() && unsafe { (*info).si_code } > 0 {
+        let address = unsafe { (*info).si_addr() } as usize;
+        report.str(" at address ").hex(address);
+    }
+    report.str(" in thread ").dec(thread_id()).str("\n");
+
+    if BACKTRACE.load(Ordering::Relaxed) {
+        let mut frames = [std::ptr::null_mut(); MAX_FRAMES];
+        let count = backtrace(&mut frames);
+        if count > 0 {
+            report.str("addy: backtrace:\n");
+            for (i, frame) in frames[..count].iter().enumerate() {
+                report.str("  #").dec(i as u64).str(" ");
+                report.hex(*frame as usize).str("\n");
+            }
+        }
+    }
+    report.flush();
+
+    /* SA_RESETHAND already restored SIG_DFL and SA_NODEFER means the signal
+     * isn't blocked, so this kills us right here.
+    	*/
+    unsafe {
+        libc::signal(signum, libc::SIG_DFL);
+        libc::raise(signum);
+        libc::_exit(128 + signum);
+    }
+}
+
+/// Installs the crash reporter. This goes straight to `sigaction(...)` and
+/// replaces any handler, including ones registered through
+/// `addy::mediate(...)`, for the configured signals.
+///
+/// The calling thread gets an alternate signal stack so a stack overflow on
+/// it can be reported. Use `addy::altstack::spawn(...)` to give other threads
+/// one too.
+///
+/// # Example
+/// ```
+/// use std::io::Read;
+/// use std::fs::File;
+/// use std::os::unix::io::FromRawFd;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     /* Crash a child process and read its report */
+///     let mut fds = [0; 2];
+///     unsafe { libc::pipe(fds.as_mut_ptr()) };
+///
+///     match unsafe { libc::fork() } {
+///         0 => {
+///             addy::crash::install(addy::crash::Config::new().fd(fds[1])).unwrap();
+///             unsafe { std::ptr::read_volatile(std::ptr::null::<u8>()) };
+///             unsafe { libc::_exit(0) };
+///         }
+///         child => {
+///             unsafe { libc::close(fds[1]) };
+///             let mut report = String::new();
+///             unsafe { File::from_raw_fd(fds[0]) }.read_to_string(&mut report)?;
+///
+///             let mut status = 0;
+///             unsafe { libc::waitpid(child, &mut status, 0) };
+///             assert!(libc::WIFSIGNALED(status));
+///             assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+///             assert!(report.contains("fatal signal SIGSEGV"));
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn install(config: Config) -> Result<(), Error> {
+    FD.store(config.fd, Ordering::Relaxed);
+    BACKTRACE.store(config.backtrace, Ordering::Relaxed);
+
+    /* The first backtrace() call may load libgcc, get that over with now
+     * rather than inside the handler.
+    	*/
+    if config.backtrace {
+        let mut frames = [std::ptr::null_mut(); MAX_FRAMES];
+        backtrace(&mut frames);
+    }
+
+    crate::altstack::ensure_for_current_thread(config.alt_stack_size)
+        .map_err(|err| Error::from_errno(err.raw_os_error().unwrap_or(0)))?;
+
+    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+        crash_handler;
+    for signal in config.signals {
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            action.sa_flags =
+                libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_NODEFER | libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal as libc::c_int, &action, std::ptr::null_mut()) == -1 {
+                return Err(Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
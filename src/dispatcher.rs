@@ -0,0 +1,178 @@
+/* Caller driven Event Loop.
+ *
+ * Normally the first addy::mediate(...) spawns a thread that runs the Event
+ * Loop. A Dispatcher sets the Event Loop up without one, the application runs
+ * it whenever it likes. Handlers write a byte to a pipe so the Dispatcher can
+ * be plugged into an existing poll(2)/epoll(7) loop.
+*/
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::kernel::{self, WAKE};
+use crate::{Driver, Error, EventLoop};
+
+/// Runs Addy's Event Loop on the caller's thread instead of a background
+/// one. Handlers are still installed and callbacks registered as usual, but
+/// nothing happens until the Dispatcher is polled.
+///
+/// It has to be created before anything else starts the Event Loop (the
+/// first `addy::mediate(...)` does). Dropping it stops the Event Loop for
+/// good: every signal goes back to its default behaviour and later Addy
+/// calls return `Error::CallFailed`.
+///
+/// Calls that wait for the Event Loop to answer, like `.try_register(...)`,
+/// return `Error::CallerDriven` instead, they'd never return if the thread
+/// waiting is the one that polls.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use addy::{Dispatcher, SIGUSR1};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let mut dispatcher = Dispatcher::new()?;
+///
+///     let called = Arc::new(AtomicBool::new(false));
+///     let flag = called.clone();
+///     addy::mediate(SIGUSR1)
+///             .register("flag", move |_signal| flag.store(true, Ordering::SeqCst))?
+///             .enable()?;
+///
+///     /* Nothing is installed until the Dispatcher gets to it */
+///     dispatcher.poll();
+///
+///     SIGUSR1.raise()?;
+///     dispatcher.run_once(Duration::from_secs(5));
+///     assert!(called.load(Ordering::SeqCst));
+///     Ok(())
+/// }
+/// ```
+pub struct Dispatcher {
+    event_loop: EventLoop,
+    /* Read end of the wake up pipe, the write end is in WAKE */
+    wake: RawFd,
+}
+
+impl std::fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("fd", &self.wake)
+            .finish()
+    }
+}
+
+/* A non-blocking, close-on-exec pipe. pipe2(...) isn't everywhere. */
+fn pipe() -> Result<[RawFd; 2], Error> {
+    let mut fds = [-1; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) == -1 {
+            return Err(Error::last_os_error());
+        }
+        for fd in &fds {
+            libc::fcntl(*fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    Ok(fds)
+}
+
+impl Dispatcher {
+    /// Sets up the Event Loop without spawning a thread for it. Returns
+    /// `Error::AlreadyStarted` if the Event Loop is already running.
+    pub fn new() -> Result<Dispatcher, Error> {
+        let [read, write] = pipe()?;
+        match crate::start(Driver::Caller) {
+            Some(event_loop) => {
+                WAKE.store(write, Ordering::SeqCst);
+                Ok(Dispatcher {
+                    event_loop,
+                    wake: read,
+                })
+            }
+            None => {
+                unsafe {
+                    libc::close(read);
+                    libc::close(write);
+                }
+                Err(Error::AlreadyStarted)
+            }
+        }
+    }
+
+    /* Empties the wake up pipe */
+    fn drain(&self) {
+        let mut buffer = [0u8; 64];
+        while unsafe {
+            libc::read(
+                self.wake,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        } > 0
+        {}
+    }
+
+    /// Handles everything that is pending (signals, registrations, removals
+    /// and so on) without blocking. Returns how many actions were handled.
+    pub fn poll(&mut self) -> usize {
+        self.drain();
+        let mut handled = 0;
+        while let Ok(action) = self.event_loop.receiver.try_recv() {
+            self.event_loop.handle(action);
+            handled += 1;
+        }
+        handled
+    }
+
+    /// Like `.poll()`, but if nothing is pending waits up to `timeout` for
+    /// something to arrive. Returns how many actions were handled, 0 if it
+    /// timed out.
+    pub fn run_once(&mut self, timeout: Duration) -> usize {
+        let handled = self.poll();
+        if handled > 0 {
+            return handled;
+        }
+        match self.event_loop.receiver.recv_timeout(timeout) {
+            Ok(action) => {
+                self.event_loop.handle(action);
+                1 + self.poll()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// Handles actions as they arrive, forever. This is what the background
+    /// thread does when there is no Dispatcher.
+    pub fn run(&mut self) {
+        /* Nobody else empties the pipe, a full one would lose wake ups */
+        while let Ok(action) = self.event_loop.receiver.recv() {
+            self.drain();
+            self.event_loop.handle(action);
+        }
+    }
+}
+
+/// The read end of a pipe that becomes readable when a signal (or timer)
+/// arrives. Register it with poll/epoll and call `.poll()` when it's ready.
+/// `.poll()` empties it.
+///
+/// Registrations and other `SignalHandle` calls don't make it readable, they
+/// are applied on the next `.poll()`.
+impl AsRawFd for Dispatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.wake
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        let write = kernel::unwake();
+        unsafe {
+            libc::close(write);
+            libc::close(self.wake);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+/* What the handler knew about a signal when it arrived.
+ *
+ * fn c_handler() reads the siginfo_t the kernel hands it into a SignalEvent
+ * and sends that to the Event Loops, instead of just the signal number.
+*/
+use std::time::Instant;
+
+use crate::{Signal, SIGCHLD};
+
+/// A single delivery of a signal, as seen by the handler. Callbacks
+/// registered with `SignalHandle::register_event(...)` receive it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalEvent {
+    signal: Signal,
+    code: libc::c_int,
+    pid: Option<libc::pid_t>,
+    uid: Option<libc::uid_t>,
+    received: Instant,
+    coalesced: u64,
+    dropped: u64,
+}
+
+/* The sender fields of a siginfo_t are only filled in for signals a process
+ * sent, and for SIGCHLD where they describe the child.
+*/
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn has_sender(signal: Signal, code: libc::c_int) -> bool {
+    matches!(code, libc::SI_USER | libc::SI_QUEUE | libc::SI_TKILL) || signal == SIGCHLD
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn has_sender(signal: Signal, code: libc::c_int) -> bool {
+    code <= 0 || signal == SIGCHLD
+}
+
+impl SignalEvent {
+    /// Makes up an event, as if `signal` was sent by nobody in particular
+    /// just now. Useful in tests, real ones come from the handler.
+    pub fn new(signal: Signal) -> Self {
+        SignalEvent {
+            signal,
+            code: 0,
+            pid: None,
+            uid: None,
+            received: Instant::now(),
+            coalesced: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Sets who the made up event claims sent it.
+    pub fn with_sender(mut self, pid: libc::pid_t, uid: libc::uid_t) -> Self {
+        self.pid = Some(pid);
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Sets the made up event's `si_code`.
+    pub fn with_code(mut self, code: libc::c_int) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Sets the made up event's running totals of coalesced and dropped
+    /// deliveries.
+    pub fn with_counts(mut self, coalesced: u64, dropped: u64) -> Self {
+        self.coalesced = coalesced;
+        self.dropped = dropped;
+        self
+    }
+
+    /* Only called from inside fn c_handler() */
+    pub(crate) fn from_siginfo(signal: Signal, info: *const libc::siginfo_t) -> Self {
+        let mut event = SignalEvent::new(signal);
+        if info.is_null() {
+            return event;
+        }
+        unsafe {
+            event.code = (*info).si_code;
+            if has_sender(signal, event.code) {
+                event.pid = Some((*info).si_pid());
+                event.uid = Some((*info).si_uid());
+            }
+        }
+        event
+    }
+
+    /// The signal that arrived.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// The raw `si_code`, e.g. `SI_USER` for `kill(...)` or `SI_QUEUE` for
+    /// `sigqueue(...)`.
+    pub fn code(&self) -> libc::c_int {
+        self.code
+    }
+
+    /// The pid of the process that sent the signal (for SIGCHLD, the child),
+    /// if the kernel said.
+    pub fn pid(&self) -> Option<libc::pid_t> {
+        self.pid
+    }
+
+    /// The real user id of the process that sent the signal, if the kernel
+    /// said.
+    pub fn uid(&self) -> Option<libc::uid_t> {
+        self.uid
+    }
+
+    /// When the handler ran.
+    pub fn received(&self) -> Instant {
+        self.received
+    }
+
+    /// How many deliveries of this signal, this one included, arrived while
+    /// an earlier one was still waiting for an Event Loop. The kernel merges
+    /// a signal that arrives while the same one is pending, so a burst like
+    /// that may have been more signals than there were events.
+    ///
+    /// It's a running total: if it went up since the last event, re-scan
+    /// whatever the signal is about (e.g. every child on SIGCHLD) instead of
+    /// assuming one event per occurrence.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// How many deliveries of this signal, up until this one, Addy couldn't
+    /// pass on to an Event Loop. A running total like `.coalesced()`.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,329 @@
+/* Where callbacks run.
+ *
+ * By default every callback runs inline on the Event Loop thread, so a slow
+ * one holds up every signal and every .register()/.remove() queued behind it.
+ * Callbacks registered with SignalHandle .register_with(...) can instead be
+ * handed off to a thread of their own or to a shared, bounded Pool. The Event
+ * Loop only pushes a job onto a queue and moves on.
+*/
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::{CBPointer, Error, SignalEvent};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/************
+ * OVERFLOW *
+ ************/
+
+/// What to do with a new job when a callback's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Throw away the oldest queued job to make room.
+    DropOldest,
+    /// Throw away the new job.
+    DropNewest,
+    /// Wait for room. This blocks the Event Loop, and with it every other
+    /// signal, until a worker catches up.
+    Block,
+}
+
+/*************
+ * EXECUTION *
+ *************/
+
+/// Where a callback registered with `SignalHandle::register_with(...)` runs.
+///
+/// # Example
+/// ```
+/// use addy::{Execution, Overflow, Pool, SIGUSR1, SIGUSR2};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     /* Slow, so it gets a thread of its own */
+///     addy::mediate(SIGUSR1)
+///             .register_with(
+///                 "flush",
+///                 Execution::Dedicated { queue: 8, overflow: Overflow::DropOldest },
+///                 |_signal| { /* Flush the database */ },
+///             )?
+///             .enable()?;
+///
+///     /* Share four threads between a few callbacks */
+///     let pool = Pool::new(4, 64, Overflow::Block)?;
+///     addy::mediate(SIGUSR2)
+///             .register_with("report", Execution::Pool(pool.clone()), |_signal| {})?
+///             .register_with("rotate", Execution::Pool(pool), |_signal| {})?
+///             .enable()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub enum Execution {
+    /// Run on the Event Loop thread, like `.register(...)` does.
+    #[default]
+    Inline,
+    /// Run on a thread owned by this callback, which exits when the
+    /// callback is removed. At most `queue` calls wait to run.
+    Dedicated {
+        /// How many calls can be waiting before `overflow` kicks in.
+        queue: usize,
+        /// What to do when the queue is full.
+        overflow: Overflow,
+    },
+    /// Run on a shared `Pool`.
+    Pool(Pool),
+}
+
+/********
+ * POOL *
+ ********/
+
+/// A fixed number of threads that callbacks can share. Each callback has its
+/// own bounded queue and runs on at most one thread at a time, so a burst for
+/// a slow callback doesn't hold up the others. Cloning it is cheap and gives
+/// another handle to the same threads. The threads exit once every handle
+/// and every callback using the pool has been dropped.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    executor: Arc<Executor>,
+}
+
+impl Pool {
+    /// Starts `threads` worker threads (at least one). At most `queue`
+    /// calls of each callback wait to run, after that `overflow` decides
+    /// what happens.
+    pub fn new(threads: usize, queue: usize, overflow: Overflow) -> Result<Pool, Error> {
+        Ok(Pool {
+            executor: Executor::spawn("addy-pool", threads.max(1), queue, overflow)?,
+        })
+    }
+
+    /// How many calls have been thrown away because a queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.executor.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/************
+ * EXECUTOR *
+ ************/
+
+struct State {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+/* What the workers take from. Holds at most one job per callback, the
+ * callbacks' own queues are where calls wait.
+*/
+struct Queue {
+    state: Mutex<State>,
+    not_empty: Condvar,
+}
+
+impl Queue {
+    fn push(&self, job: Job) {
+        self.state.lock().unwrap().jobs.push_back(job);
+        self.not_empty.notify_one();
+    }
+
+    /* Blocks for the next job. None once closed and drained. */
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                return Some(job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+}
+
+/* Owns the worker threads through their queue. The workers only hold the
+ * Queue, so dropping the last Executor closes it and lets them exit.
+*/
+struct Executor {
+    queue: Arc<Queue>,
+    /* How many calls each callback can have waiting */
+    limit: usize,
+    overflow: Overflow,
+    dropped: AtomicU64,
+}
+
+impl std::fmt::Debug for Executor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Executor")
+            .field("limit", &self.limit)
+            .field("overflow", &self.overflow)
+            .finish()
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().closed = true;
+        self.queue.not_empty.notify_all();
+    }
+}
+
+impl Executor {
+    fn spawn(
+        name: &str,
+        threads: usize,
+        limit: usize,
+        overflow: Overflow,
+    ) -> Result<Arc<Executor>, Error> {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(State {
+                jobs: VecDeque::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+        });
+        /* Closes the queue if a later thread fails to spawn */
+        let executor = Arc::new(Executor {
+            queue: queue.clone(),
+            /* A zero limit could never make progress */
+            limit: limit.max(1),
+            overflow,
+            dropped: AtomicU64::new(0),
+        });
+
+        for _ in 0..threads {
+            let queue = queue.clone();
+            thread::Builder::new()
+                .name(name.into())
+                .spawn(move || {
+                    while let Some(job) = queue.pop() {
+                        job();
+                    }
+                })
+                .map_err(|err| Error::from_errno(err.raw_os_error().unwrap_or(0)))?;
+        }
+        Ok(executor)
+    }
+}
+
+/**********
+ * SERIAL *
+ **********/
+
+struct Calls {
+    events: VecDeque<SignalEvent>,
+    /* A job for this callback is queued or running */
+    scheduled: bool,
+}
+
+/* One callback's queue. Only one job per callback is ever handed to the
+ * workers, it runs one call and queues itself again if there are more, so
+ * the callback never runs twice at once and never keeps a worker waiting.
+*/
+struct Serial<F> {
+    callback: Mutex<F>,
+    calls: Mutex<Calls>,
+    not_full: Condvar,
+    executor: Arc<Executor>,
+}
+
+impl<F> Serial<F>
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    /* Called from the Event Loop */
+    fn push(self: &Arc<Self>, event: SignalEvent) {
+        let executor = &self.executor;
+        let mut calls = self.calls.lock().unwrap();
+        while calls.events.len() >= executor.limit {
+            match executor.overflow {
+                Overflow::DropOldest => {
+                    calls.events.pop_front();
+                    executor.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Overflow::DropNewest => {
+                    executor.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Overflow::Block => calls = self.not_full.wait(calls).unwrap(),
+            }
+        }
+        calls.events.push_back(event);
+        if !calls.scheduled {
+            calls.scheduled = true;
+            drop(calls);
+            self.schedule();
+        }
+    }
+
+    fn schedule(self: &Arc<Self>) {
+        let serial = self.clone();
+        self.executor.queue.push(Box::new(move || serial.run()));
+    }
+
+    /* Called from a worker */
+    fn run(self: &Arc<Self>) {
+        let event = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.events.pop_front() {
+                Some(event) => event,
+                None => {
+                    calls.scheduled = false;
+                    return;
+                }
+            }
+        };
+        self.not_full.notify_one();
+
+        /* A panicking callback shouldn't take the worker down with it, or
+         * leave the callback scheduled forever. The panic hook still
+         * reports it, and a poisoned lock doesn't make it unusable.
+        	*/
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let callback = self.callback.lock().unwrap_or_else(|err| err.into_inner());
+            callback(&event);
+        }));
+
+        /* Back of the line, the other callbacks get a turn first */
+        let mut calls = self.calls.lock().unwrap();
+        if calls.events.is_empty() {
+            calls.scheduled = false;
+        } else {
+            drop(calls);
+            self.schedule();
+        }
+    }
+}
+
+/* Wraps callback so calling it from the Event Loop runs it according to
+ * execution. Callbacks handed off to other threads run one call at a time,
+ * same as on the Event Loop, so they only need to be Send.
+*/
+pub(crate) fn wrap<F>(execution: Execution, callback: F) -> Result<CBPointer, Error>
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    let executor = match execution {
+        Execution::Inline => return Ok(Box::new(callback)),
+        Execution::Dedicated { queue, overflow } => {
+            Executor::spawn("addy-callback", 1, queue, overflow)?
+        }
+        Execution::Pool(pool) => pool.executor,
+    };
+
+    let serial = Arc::new(Serial {
+        callback: Mutex::new(callback),
+        calls: Mutex::new(Calls {
+            events: VecDeque::new(),
+            scheduled: false,
+        }),
+        not_full: Condvar::new(),
+        executor,
+    });
+    Ok(Box::new(move |event: &SignalEvent| serial.push(*event)))
+}
@@ -0,0 +1,271 @@
+//! What a process does with each signal, read from /proc.
+//!
+//! A parent like nohup, a shell or a container runtime can start a process
+//! with signals already ignored or blocked, and both are inherited across
+//! `exec`. `addy::inspect::current()` shows what the calling process was
+//! handed, `addy::inspect::process(pid)` shows any other process.
+//!
+//! Ignored and caught signals are shared by the whole process. Blocked and
+//! pending signals are per thread, so they're also reported for every thread
+//! under /proc/<pid>/task. A signal sent to the process stays pending until
+//! some thread has it unblocked.
+//!
+//! # Example
+//! ```
+//! use addy::SIGTERM;
+//!
+//! fn main() -> Result<(), addy::Error> {
+//!     let signals = addy::inspect::current()?;
+//!     if signals.ignored().contains(SIGTERM) {
+//!         eprintln!("SIGTERM was ignored before we started");
+//!     }
+//!     for thread in signals.threads() {
+//!         if thread.blocked().contains(SIGTERM) {
+//!             eprintln!("Thread {} blocks SIGTERM", thread.tid());
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+
+use crate::{Error, Signal};
+
+/*************
+ * SIGNALSET *
+ *************/
+
+/// A set of signals, real-time ones included. Bit n - 1 of the mask is
+/// signal n, the same layout /proc uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SignalSet {
+    mask: u64,
+}
+
+impl SignalSet {
+    /// The set with the signals in `mask` in it.
+    pub fn from_mask(mask: u64) -> Self {
+        SignalSet { mask }
+    }
+
+    /// The set as a mask, bit n - 1 being signal n.
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    /// True if `signal` is in the set.
+    pub fn contains<S: Into<Signal>>(&self, signal: S) -> bool {
+        self.contains_number(signal.into() as libc::c_int)
+    }
+
+    /// True if the signal numbered `signum` is in the set, for real-time
+    /// signals which have no `Signal`.
+    pub fn contains_number(&self, signum: libc::c_int) -> bool {
+        (1..=64).contains(&signum) && self.mask & (1u64 << (signum - 1)) != 0
+    }
+
+    /// True if there are no signals in the set.
+    pub fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+
+    /// How many signals are in the set.
+    pub fn len(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    /// The number of every signal in the set, lowest first.
+    pub fn numbers(&self) -> impl Iterator<Item = libc::c_int> {
+        let set = *self;
+        (1..=64).filter(move |signum| set.contains_number(*signum))
+    }
+
+    /// Every signal in the set Addy has a `Signal` for, lowest first.
+    /// Real-time signals are left out, see `.numbers()`.
+    pub fn signals(&self) -> impl Iterator<Item = Signal> {
+        self.numbers()
+            .filter_map(|signum| Signal::try_from(signum).ok())
+    }
+}
+
+/* Names separated by spaces, real-time signals as SIGRTMIN+n */
+impl std::fmt::Display for SignalSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, signum) in self.numbers().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match Signal::try_from(signum) {
+                Ok(signal) => f.write_str(signal.as_str())?,
+                Err(_) if signum >= libc::SIGRTMIN() => {
+                    write!(f, "SIGRTMIN+{}", signum - libc::SIGRTMIN())?
+                }
+                Err(_) => write!(f, "{}", signum)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/*************
+ * PROCESSES *
+ *************/
+
+/// The signal dispositions of a process, from `addy::inspect::process(...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessSignals {
+    pid: libc::pid_t,
+    blocked: SignalSet,
+    ignored: SignalSet,
+    caught: SignalSet,
+    pending: SignalSet,
+    threads: Vec<ThreadSignals>,
+}
+
+impl ProcessSignals {
+    /// The process these are for.
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Signals the main thread has blocked.
+    pub fn blocked(&self) -> SignalSet {
+        self.blocked
+    }
+
+    /// Signals the process ignores.
+    pub fn ignored(&self) -> SignalSet {
+        self.ignored
+    }
+
+    /// Signals the process has a handler for.
+    pub fn caught(&self) -> SignalSet {
+        self.caught
+    }
+
+    /// Signals sent to the process that no thread has taken yet.
+    pub fn pending(&self) -> SignalSet {
+        self.pending
+    }
+
+    /// Every thread of the process, by tid. Threads that exited while they
+    /// were being read are left out.
+    pub fn threads(&self) -> &[ThreadSignals] {
+        &self.threads
+    }
+}
+
+/// The blocked and pending signals of one thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadSignals {
+    tid: libc::pid_t,
+    blocked: SignalSet,
+    pending: SignalSet,
+}
+
+impl ThreadSignals {
+    /// The thread these are for.
+    pub fn tid(&self) -> libc::pid_t {
+        self.tid
+    }
+
+    /// Signals the thread has blocked.
+    pub fn blocked(&self) -> SignalSet {
+        self.blocked
+    }
+
+    /// Signals sent to this thread alone that it hasn't taken yet.
+    pub fn pending(&self) -> SignalSet {
+        self.pending
+    }
+}
+
+/// Reads the blocked, ignored, caught and pending signals of `pid` and each
+/// of its threads.
+///
+/// Returns `Error::NoSuchProcess` if there is no such process and
+/// `Error::PermissionDenied` if its /proc entry can't be read.
+///
+/// # Example
+/// ```
+/// use std::process::Command;
+/// use addy::SIGKILL;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mut child = Command::new("sleep").arg("10").spawn()?;
+///     let signals = addy::inspect::process(child.id() as libc::pid_t)?;
+///     assert!(!signals.caught().contains(SIGKILL));
+///
+///     child.kill()?;
+///     child.wait()?;
+///     Ok(())
+/// }
+/// ```
+pub fn process(pid: libc::pid_t) -> Result<ProcessSignals, Error> {
+    if pid <= 0 {
+        return Err(Error::InvalidArgument);
+    }
+    let status = read(&format!("/proc/{}/status", pid))?;
+
+    let mut threads = Vec::new();
+    let tasks = std::fs::read_dir(format!("/proc/{}/task", pid)).map_err(from_io)?;
+    for task in tasks {
+        let tid = match task
+            .ok()
+            .and_then(|task| task.file_name().to_str()?.parse().ok())
+        {
+            Some(tid) => tid,
+            None => continue,
+        };
+        /* The thread may have exited since the directory was listed */
+        let status = match read(&format!("/proc/{}/task/{}/status", pid, tid)) {
+            Ok(status) => status,
+            Err(Error::NoSuchProcess) => continue,
+            Err(err) => return Err(err),
+        };
+        threads.push(ThreadSignals {
+            tid,
+            blocked: field(&status, "SigBlk")?,
+            pending: field(&status, "SigPnd")?,
+        });
+    }
+    threads.sort_by_key(|thread| thread.tid);
+
+    Ok(ProcessSignals {
+        pid,
+        blocked: field(&status, "SigBlk")?,
+        ignored: field(&status, "SigIgn")?,
+        caught: field(&status, "SigCgt")?,
+        pending: field(&status, "ShdPnd")?,
+        threads,
+    })
+}
+
+/// Like `addy::inspect::process(...)` for the calling process.
+pub fn current() -> Result<ProcessSignals, Error> {
+    process(std::process::id() as libc::pid_t)
+}
+
+fn read(path: &str) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(from_io)
+}
+
+/* A missing /proc entry means the process (or thread) is gone */
+fn from_io(err: std::io::Error) -> Error {
+    match err.kind() {
+        ErrorKind::NotFound => Error::NoSuchProcess,
+        ErrorKind::PermissionDenied => Error::PermissionDenied,
+        _ => Error::from_errno(err.raw_os_error().unwrap_or(0)),
+    }
+}
+
+/* Parses a line like "SigBlk:	0000000000010000" out of a status file */
+fn field(status: &str, name: &str) -> Result<SignalSet, Error> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+        .map(SignalSet::from_mask)
+        .ok_or(Error::InvalidArgument)
+}
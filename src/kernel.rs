@@ -0,0 +1,229 @@
+/* The process wide, kernel facing side of Addy.
+ *
+ * Handlers and dispositions belong to the whole process but every Mediator
+ * has its own callbacks and Event Loop. fn c_handler() forwards each signal
+ * to every live Mediator, and a signal stays pointed at fn c_handler() for as
+ * long as at least one Mediator is handling it.
+*/
+use std::sync::atomic::{AtomicI32, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{report, stats, Action, Error, HandlerOptions, Signal, SignalEvent, NUM_SIGNALS};
+
+/* Most Mediators that can be alive at once, including the default one */
+const MAX_MEDIATORS: usize = 64;
+
+/* Slot 0 belongs to the default Mediator behind addy::mediate(...) */
+const DEFAULT: usize = 0;
+
+/* C FFI MESSAGE PASSERS
+ *
+ * A sender to each live Mediator's Event Loop. Signal handlers can't take a
+ * lock, so these are plain atomic pointers. A slot is only freed once no
+ * handler can still be reading it, see fn detach().
+*/
+static SLOTS: [AtomicPtr<Sender<Action>>; MAX_MEDIATORS] =
+    [const { AtomicPtr::new(std::ptr::null_mut()) }; MAX_MEDIATORS];
+
+/* Number of handlers currently walking SLOTS */
+static IN_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/* Write end of the Dispatcher's wake up pipe, -1 when the default Event Loop
+ * runs on its own thread. Handlers write a byte to it so a caller polling
+ * Dispatcher::as_raw_fd() knows there is something to do.
+*/
+pub(crate) static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+/* Per signal count of Mediators that want it pointed at fn c_handler().
+ * Only the Event Loops touch it, never a handler.
+*/
+static HANDLING: Mutex<[usize; NUM_SIGNALS as usize]> = Mutex::new([0; NUM_SIGNALS as usize]);
+
+/*********
+ * SLOTS *
+ *********/
+
+/* Gives the default Mediator its slot. Called ONCE, it's never detached. */
+pub(crate) fn attach_default(sender: Sender<Action>) {
+    let sender = Box::into_raw(Box::new(sender));
+    SLOTS[DEFAULT].store(sender, Ordering::SeqCst);
+}
+
+/* Finds a free slot for a Mediator, None if they're all taken */
+pub(crate) fn attach(sender: Sender<Action>) -> Option<usize> {
+    let sender = Box::into_raw(Box::new(sender));
+    for (index, slot) in SLOTS.iter().enumerate().skip(DEFAULT + 1) {
+        if slot
+            .compare_exchange(
+                std::ptr::null_mut(),
+                sender,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+        {
+            return Some(index);
+        }
+    }
+    /* Nobody else ever saw it */
+    drop(unsafe { Box::from_raw(sender) });
+    None
+}
+
+/* Empties a slot, waiting out any handler that might still be using it */
+pub(crate) fn detach(index: usize) {
+    let sender = SLOTS[index].swap(std::ptr::null_mut(), Ordering::SeqCst);
+    while IN_HANDLER.load(Ordering::SeqCst) != 0 {
+        thread::yield_now();
+    }
+    if !sender.is_null() {
+        drop(unsafe { Box::from_raw(sender) });
+    }
+}
+
+/* Stops handlers waking the Dispatcher and returns the write end of its
+ * pipe once none of them can still be writing to it, so it can be closed.
+*/
+pub(crate) fn unwake() -> libc::c_int {
+    let wake = WAKE.swap(-1, Ordering::SeqCst);
+    while IN_HANDLER.load(Ordering::SeqCst) != 0 {
+        thread::yield_now();
+    }
+    wake
+}
+
+/************
+ * HANDLERS *
+ ************/
+
+/* The rest are only called from inside signal handlers */
+
+/* None if the slot is empty, otherwise whether the send went through. We
+ * can't return an error from across the kernel boundary, so callers can
+ * only count it.
+*/
+fn send(index: usize, action: Action) -> Option<bool> {
+    let sender = SLOTS[index].load(Ordering::SeqCst);
+    if sender.is_null() {
+        return None;
+    }
+    Some(unsafe { &*sender }.send(action).is_ok())
+}
+
+/* Wake up a caller polling the Dispatcher's fd. If the pipe is full there's
+ * already a wake up pending. Only called while counted in IN_HANDLER so
+ * fn unwake() knows when the fd is no longer used.
+*/
+fn wake() {
+    let wake = WAKE.load(Ordering::SeqCst);
+    if wake >= 0 {
+        unsafe {
+            libc::write(wake, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/* Tells every Mediator the signal arrived */
+pub(crate) fn broadcast(event: SignalEvent) {
+    IN_HANDLER.fetch_add(1, Ordering::SeqCst);
+    for index in 0..MAX_MEDIATORS {
+        match send(index, Action::Call(event)) {
+            Some(true) => stats::queued(event.signal()),
+            Some(false) => stats::dropped(event.signal()),
+            None => {}
+        }
+    }
+    wake();
+    IN_HANDLER.fetch_sub(1, Ordering::SeqCst);
+}
+
+/* Passes an Action to the default Mediator only */
+pub(crate) fn send_default(action: Action) {
+    IN_HANDLER.fetch_add(1, Ordering::SeqCst);
+    let _ = send(DEFAULT, action);
+    wake();
+    IN_HANDLER.fetch_sub(1, Ordering::SeqCst);
+}
+
+/****************
+ * DISPOSITIONS *
+ ****************/
+
+/* SigAction structs to represent SIG_DFL and SIG_IGN. These are passed to
+ * libc::sigaction(...) to tell it what to do when a signal is called. They
+ * tell it to perform the default action or ignore the signal respectively.
+ * The struct pointing at fn c_handler() is built from the signal's
+ * HandlerOptions.
+ *
+ * Zeroed then filled in because some architectures have extra fields and
+ * need a proper sa_mask generated to compile.
+*/
+fn disposition(handler: libc::sighandler_t) -> libc::sigaction {
+    unsafe {
+        let mut action = std::mem::zeroed::<libc::sigaction>();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_SIGINFO;
+        /* Have to create a mask for the structs that enables all singals */
+        libc::sigfillset(&mut action.sa_mask);
+        action
+    }
+}
+
+fn set(signal: Signal, action: &libc::sigaction) {
+    let ret = unsafe { libc::sigaction(signal as libc::c_int, action, std::ptr::null_mut()) };
+    if ret == -1 {
+        report::sigaction(signal, Error::last_os_error());
+    }
+}
+
+/* Tells the process to ignore the interrupt */
+pub(crate) fn ignore(signal: Signal) {
+    set(signal, &disposition(libc::SIG_IGN));
+}
+
+/* Sets the interrupt handler to the default value */
+pub(crate) fn default(signal: Signal) {
+    set(signal, &disposition(libc::SIG_DFL));
+}
+
+/* Points the interrupt at fn c_handler() with its options */
+fn install(signal: Signal, options: &HandlerOptions) {
+    set(signal, &options.sigaction(crate::c_handler_address()));
+}
+
+/* A Mediator starts handling the signal */
+pub(crate) fn claim(signal: Signal, options: &HandlerOptions) {
+    let mut handling = HANDLING.lock().unwrap();
+    handling[crate::index(signal)] += 1;
+    install(signal, options);
+}
+
+/* A Mediator that is handling the signal changed its options. The last
+ * Mediator to set them wins.
+*/
+pub(crate) fn reinstall(signal: Signal, options: &HandlerOptions) {
+    let _handling = HANDLING.lock().unwrap();
+    install(signal, options);
+}
+
+/* A Mediator stops handling the signal (if it was) and wants it to behave
+ * like disposition. Only applied once no other Mediator is handling it.
+*/
+pub(crate) fn release(signal: Signal, was_handling: bool, disposition: fn(Signal)) {
+    let mut handling = HANDLING.lock().unwrap();
+    let count = &mut handling[crate::index(signal)];
+    if was_handling {
+        *count = count.saturating_sub(1);
+    }
+    if *count == 0 {
+        disposition(signal);
+    }
+}
+
+/* SA_RESETHAND already put SIG_DFL back for everyone */
+pub(crate) fn forget(signal: Signal) {
+    let mut handling = HANDLING.lock().unwrap();
+    handling[crate::index(signal)] = 0;
+}
@@ -84,37 +84,129 @@
     unused_import_braces,
     unused_qualifications
 )]
+/* The examples in the docs are indented with tabs */
+#![allow(clippy::tabs_in_doc_comments)]
 
 /* Standard Library */
 use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{
-    mpsc::{self, Sender},
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, Sender},
     Mutex, Once,
 };
 use std::thread;
+use std::time::Instant;
 
 /* Std Lib Adjacent Crates */
 use lazy_static::lazy_static;
-use libc;
 
 /* Thrid Party Crates */
 use fnv::FnvHashMap; // Faster for the interger keys we're using
 
+/***********
+ * MODULES *
+ ***********/
+pub mod altstack;
+pub mod children;
+mod context;
+pub mod crash;
+mod dispatcher;
+mod event;
+mod execution;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod inspect;
+mod kernel;
+mod mediator;
+mod options;
+#[cfg(feature = "serde")]
+pub mod policy;
+pub mod reload;
+mod report;
+mod send;
+mod shutdown;
+mod stats;
+pub mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timer;
+mod trace;
+pub use context::CallbackContext;
+pub use dispatcher::Dispatcher;
+pub use event::SignalEvent;
+pub use execution::{Execution, Overflow, Pool};
+pub use mediator::Mediator;
+pub use options::HandlerOptions;
+pub use report::{on_error, AddyError, Cause};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use send::queue;
+pub use send::{send, send_group, send_thread};
+pub use shutdown::{Shutdown, ShutdownBuilder, ShutdownToken};
+pub use stats::{stats, CallbackStats, SignalStats, Stats, Timing};
+
 /**********
  * ERRORS *
  **********/
 /* Use our own error instead of passing the SendError<Action> so we don't have
  * expose the Action enum publicly.
 */
-#[derive(Debug, Clone, Copy)]
-/// Addy Error type - realistically you will never see it. As it only occurs
-/// when the MPSC channel fails. MPSC channels only fail if the receiver is
-/// dropped which can only happen if the event loop thread panics somehow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Addy Error type. For the event loop functions realistically you will never
+/// see it. As it only occurs when the MPSC channel fails. MPSC channels only
+/// fail if the receiver is dropped which can only happen if the event loop
+/// thread panics somehow.
 ///
 /// If it does fail, there is no way to recover, future Addy calls will fail.
+///
+/// The functions that send signals (addy::send(...) and friends) return the
+/// remaining variants when the kernel refuses the request.
 pub enum Error {
     /// Returned when a function call on a SignalHandler fails.
     CallFailed,
+    /// The target process, process group or thread does not exist (ESRCH).
+    NoSuchProcess,
+    /// The caller is not allowed to signal the target (EPERM).
+    PermissionDenied,
+    /// The limit of queued real-time signals has been reached (EAGAIN).
+    QueueFull,
+    /// An argument was rejected (EINVAL), e.g. a non-positive pid or an
+    /// unknown shutdown phase.
+    InvalidArgument,
+    /// Any other errno returned by the kernel.
+    Os(i32),
+    /// The Event Loop is already running, e.g. `Dispatcher::new()` was
+    /// called after `addy::mediate(...)`.
+    AlreadyStarted,
+    /// `Mediator::new()` was called with the maximum number of Mediators
+    /// already alive.
+    TooManyMediators,
+    /// `.try_register(...)` found a callback with the same name.
+    AlreadyRegistered,
+    /// Called from a callback running on the Event Loop, where waiting for
+    /// the Event Loop would never return.
+    InCallback,
+    /// The Event Loop is driven by a `Dispatcher`, which may be the calling
+    /// thread, so waiting for it might never return.
+    CallerDriven,
+}
+
+impl Error {
+    /* Maps an errno returned by a libc call to the matching variant */
+    pub(crate) fn from_errno(errno: i32) -> Error {
+        match errno {
+            libc::ESRCH => Error::NoSuchProcess,
+            libc::EPERM => Error::PermissionDenied,
+            libc::EAGAIN => Error::QueueFull,
+            libc::EINVAL => Error::InvalidArgument,
+            errno => Error::Os(errno),
+        }
+    }
+
+    /* Builds an Error out of the errno left behind by the last libc call */
+    pub(crate) fn last_os_error() -> Error {
+        let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Error::from_errno(errno)
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -124,6 +216,20 @@ impl std::fmt::Display for Error {
                 f,
                 "Addy function call failed to send. The MPSC and/or event loop thread has closed."
             ),
+            Error::NoSuchProcess => write!(f, "No such process, process group or thread."),
+            Error::PermissionDenied => write!(f, "Not permitted to send a signal to the target."),
+            Error::QueueFull => write!(f, "The real-time signal queue is full."),
+            Error::InvalidArgument => write!(f, "Invalid argument."),
+            Error::Os(errno) => write!(f, "{}", std::io::Error::from_raw_os_error(*errno)),
+            Error::AlreadyStarted => write!(f, "The Addy Event Loop has already been started."),
+            Error::TooManyMediators => write!(f, "Too many Addy Mediators are alive."),
+            Error::AlreadyRegistered => {
+                write!(f, "A callback with that name is already registered.")
+            }
+            Error::InCallback => write!(f, "Can't wait for the Event Loop from a callback on it."),
+            Error::CallerDriven => {
+                write!(f, "Can't wait for an Event Loop driven by a Dispatcher.")
+            }
         }
     }
 }
@@ -148,7 +254,8 @@ impl std::error::Error for Error {
  *
  * CBP wraps CBPointer so Debug can be implemented for it
 */
-type CBPointer = Box<dyn Fn(Signal) -> () + Send>;
+type CBPointer = Box<dyn Fn(&SignalEvent) + Send>;
+#[allow(clippy::upper_case_acronyms)]
 struct CBP(CBPointer);
 impl std::fmt::Debug for CBP {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -156,17 +263,77 @@ impl std::fmt::Debug for CBP {
     }
 }
 
+/* Work the subsystems want done on the Event Loop thread, wrapped so Debug
+ * can be implemented for it.
+*/
+struct Job(Box<dyn FnOnce() + Send>);
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Job")
+    }
+}
+
+/* A callback's name, scoped to the namespace of the SignalHandle that
+ * registered it. Callbacks registered without one share the "" namespace.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Name {
+    namespace: String,
+    name: String,
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.namespace.is_empty() {
+            f.write_str(&self.name)
+        } else {
+            write!(f, "{}::{}", self.namespace, self.name)
+        }
+    }
+}
+
+/* Everything the Event Loop needs to add a callback */
+#[derive(Debug)]
+struct Registration {
+    signal: Signal,
+    name: Name,
+    tags: Vec<String>,
+    cb: CBP,
+    /* Set by .try_register(), which must not replace a callback. Told
+     * whether the callback was added.
+    	*/
+    added: Option<Sender<bool>>,
+}
+
 /* This enum is what is message passed to the Event Loop to tell it what
  * action to take.
 */
 #[derive(Debug)]
 enum Action {
     // Used by fn c_handler(...) to tell the Event Loop an interrupt occured
-    Call(Signal),
+    Call(SignalEvent),
+    /* Used by addy::testing to run the callbacks for a made up event. The
+     * names of the callbacks that ran are sent back once they're done.
+    	*/
+    #[cfg(feature = "testing")]
+    Inject(SignalEvent, Sender<Vec<String>>),
     // Used by SignalHandle to add a named callback for the associated interrupt
-    Register(Signal, String, CBP),
+    Register(Registration),
     // Used by SignalHandle to remove a named callback from the associated interrupt
-    Remove(Signal, String),
+    Remove(Signal, Name),
+    /* Used by the subsystems (e.g. timers) to remove their callback and stop
+     * handling the interrupt if that was the last one.
+    	*/
+    Retire(Signal, Name),
+    // Used by addy::remove_tag(...) to remove every callback with the tag
+    RemoveTag(String),
+    // Used by addy::clear_namespace(...) to remove every callback in it
+    ClearNamespace(String),
+    /* Used by addy::policy to make several changes with no signal handled
+     * in between.
+    	*/
+    #[cfg(feature = "serde")]
+    Batch(Vec<Action>),
     /* Used by SignalHandle to clear all the callbacks from the associated
      * intterupt. This effectively ignores the interrupt, but the signal is
      * still handled by this library and the signal handler. If you're clearing
@@ -193,6 +360,18 @@ enum Action {
      * interrupt.
     	*/
     Resume(Signal),
+    /* Used by SignalHandle .enable_with() to change the sigaction(...) flags
+     * and mask for the interrupt. They're kept per signal so .resume()
+     * reinstalls the handler with the same configuration.
+    	*/
+    Options(Signal, HandlerOptions),
+    /* Used by the subsystems (e.g. timers) to run something on the Event Loop
+     * once every Action sent before it has been handled.
+    	*/
+    Run(Job),
+    // Used by the POSIX timer handler to tell the Event Loop timer <id> fired
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Timer(usize),
 }
 
 /***********
@@ -395,6 +574,172 @@ impl Signal {
             SIGINFO => "SIGINFO",
         }
     }
+
+    /// What the process does with the signal if nobody handles or ignores
+    /// it.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::{DefaultAction, SIGCHLD, SIGTERM};
+    ///
+    /// fn main() {
+    ///     assert_eq!(SIGTERM.default_action(), DefaultAction::Terminate);
+    ///     assert_eq!(SIGCHLD.default_action(), DefaultAction::Ignore);
+    /// }
+    /// ```
+    pub fn default_action(self) -> DefaultAction {
+        match self {
+            SIGHUP => DefaultAction::Terminate,
+            SIGINT => DefaultAction::Terminate,
+            SIGQUIT => DefaultAction::Core,
+            SIGILL => DefaultAction::Core,
+            SIGTRAP => DefaultAction::Core,
+            SIGABRT => DefaultAction::Core,
+            SIGBUS => DefaultAction::Core,
+            SIGFPE => DefaultAction::Core,
+            SIGKILL => DefaultAction::Terminate,
+            SIGUSR1 => DefaultAction::Terminate,
+            SIGSEGV => DefaultAction::Core,
+            SIGUSR2 => DefaultAction::Terminate,
+            SIGPIPE => DefaultAction::Terminate,
+            SIGALRM => DefaultAction::Terminate,
+            SIGTERM => DefaultAction::Terminate,
+            #[cfg(all(
+                any(target_os = "android", target_os = "emscripten", target_os = "linux"),
+                not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc64"))
+            ))]
+            SIGSTKFLT => DefaultAction::Terminate,
+            SIGCHLD => DefaultAction::Ignore,
+            SIGCONT => DefaultAction::Continue,
+            SIGSTOP => DefaultAction::Stop,
+            SIGTSTP => DefaultAction::Stop,
+            SIGTTIN => DefaultAction::Stop,
+            SIGTTOU => DefaultAction::Stop,
+            SIGURG => DefaultAction::Ignore,
+            SIGXCPU => DefaultAction::Core,
+            SIGXFSZ => DefaultAction::Core,
+            SIGVTALRM => DefaultAction::Terminate,
+            SIGPROF => DefaultAction::Terminate,
+            SIGWINCH => DefaultAction::Ignore,
+            SIGIO => DefaultAction::Terminate,
+            #[cfg(any(target_os = "android", target_os = "emscripten", target_os = "linux"))]
+            SIGPWR => DefaultAction::Terminate,
+            SIGSYS => DefaultAction::Core,
+            #[cfg(not(any(target_os = "android", target_os = "emscripten", target_os = "linux")))]
+            SIGEMT => DefaultAction::Core,
+            #[cfg(not(any(target_os = "android", target_os = "emscripten", target_os = "linux")))]
+            SIGINFO => DefaultAction::Ignore,
+        }
+    }
+
+    /// A short description of what the signal means, as in signal(7).
+    pub fn description(self) -> &'static str {
+        match self {
+            SIGHUP => "Hangup detected on controlling terminal or death of controlling process",
+            SIGINT => "Interrupt from keyboard",
+            SIGQUIT => "Quit from keyboard",
+            SIGILL => "Illegal Instruction",
+            SIGTRAP => "Trace/breakpoint trap",
+            SIGABRT => "Abort signal from abort(3)",
+            SIGBUS => "Bus error (bad memory access)",
+            SIGFPE => "Floating-point exception",
+            SIGKILL => "Kill signal",
+            SIGUSR1 => "User-defined signal 1",
+            SIGSEGV => "Invalid memory reference",
+            SIGUSR2 => "User-defined signal 2",
+            SIGPIPE => "Broken pipe: write to pipe with no readers",
+            SIGALRM => "Timer signal from alarm(2)",
+            SIGTERM => "Termination signal",
+            #[cfg(all(
+                any(target_os = "android", target_os = "emscripten", target_os = "linux"),
+                not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc64"))
+            ))]
+            SIGSTKFLT => "Stack fault on coprocessor",
+            SIGCHLD => "Child stopped or terminated",
+            SIGCONT => "Continue if stopped",
+            SIGSTOP => "Stop process",
+            SIGTSTP => "Stop typed at terminal",
+            SIGTTIN => "Terminal input for background process",
+            SIGTTOU => "Terminal output for background process",
+            SIGURG => "Urgent condition on socket (4.2BSD)",
+            SIGXCPU => "CPU time limit exceeded (4.2BSD)",
+            SIGXFSZ => "File size limit exceeded (4.2BSD)",
+            SIGVTALRM => "Virtual alarm clock (4.2BSD)",
+            SIGPROF => "Profiling timer expired",
+            SIGWINCH => "Window resize signal (4.3BSD, Sun)",
+            SIGIO => "I/O now possible (4.2BSD)",
+            #[cfg(any(target_os = "android", target_os = "emscripten", target_os = "linux"))]
+            SIGPWR => "Power failure (System V)",
+            SIGSYS => "Bad system call (SVr4)",
+            #[cfg(not(any(target_os = "android", target_os = "emscripten", target_os = "linux")))]
+            SIGEMT => "Emulator trap",
+            #[cfg(not(any(target_os = "android", target_os = "emscripten", target_os = "linux")))]
+            SIGINFO => "Status request from keyboard",
+        }
+    }
+}
+
+/* Lets us turn the raw signal number the kernel hands fn c_handler() back into
+ * a Signal. Fails on numbers that aren't in our enum for this platform.
+*/
+impl TryFrom<libc::c_int> for Signal {
+    type Error = libc::c_int;
+
+    fn try_from(signum: libc::c_int) -> Result<Self, Self::Error> {
+        Signal::iterator()
+            .find(|signal| *signal as libc::c_int == signum)
+            .ok_or(signum)
+    }
+}
+
+/// What a signal does to the process by default, see
+/// `Signal::default_action()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefaultAction {
+    /// Terminates the process.
+    Terminate,
+    /// Terminates the process and dumps core.
+    Core,
+    /// Nothing happens.
+    Ignore,
+    /// Stops the process.
+    Stop,
+    /// Continues the process if it is stopped.
+    Continue,
+}
+
+impl DefaultAction {
+    /// The action's name as signal(7) has it: Term, Core, Ign, Stop or Cont.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DefaultAction::Terminate => "Term",
+            DefaultAction::Core => "Core",
+            DefaultAction::Ignore => "Ign",
+            DefaultAction::Stop => "Stop",
+            DefaultAction::Continue => "Cont",
+        }
+    }
+}
+
+impl std::fmt::Display for DefaultAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/* Lets config files and command lines name signals. Takes "SIGHUP", "HUP"
+ * or any other case of either.
+*/
+impl std::str::FromStr for Signal {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_ascii_uppercase();
+        let name = name.strip_prefix("SIG").unwrap_or(&name);
+        Signal::iterator()
+            .find(|signal| &signal.as_str()[3..] == name)
+            .ok_or(Error::InvalidArgument)
+    }
 }
 
 impl AsRef<str> for Signal {
@@ -495,24 +840,81 @@ impl Signal {
 
 /* This is the callback passed to the C FF sigaction(...) - it is called with
  * three arguments. We only care about what signal was called so we free() the
- * other two, grab a copy of Sender and message pass what signal was called to
- * the Event Loop.
+ * other two and message pass what signal was called to the Event Loops.
 */
 type CVoid = *mut libc::c_void;
-fn c_handler(signal: Signal, _info: CVoid, _ucontext: CVoid) {
-    /* We're the only function that interacts with this global static copy of
-     * a sender to the Event Loop. We only read from this location, only one
-     * interrupt can be active at a time so this is SAFE.
-    	*/
-    let sender;
+extern "C" fn c_handler(signal: libc::c_int, info: *mut libc::siginfo_t, _ucontext: CVoid) {
+    /* Only signals from our own enum are ever pointed at this handler */
+    let signal = match Signal::try_from(signal) {
+        Ok(signal) => signal,
+        Err(_) => return,
+    };
+
+    let (coalesced, dropped) = stats::received(signal);
+    let event = SignalEvent::from_siginfo(signal, info).with_counts(coalesced, dropped);
+
+    /* Every Mediator gets a copy */
+    kernel::broadcast(event);
+}
+
+/* The address of fn c_handler() as sigaction(...) wants it */
+fn c_handler_address() -> libc::sighandler_t {
+    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, CVoid) = c_handler;
+    handler as libc::sighandler_t
+}
+
+/* Called when alternate signal stacks are first enabled. Adds SA_ONSTACK to
+ * every signal that already points at fn c_handler() so they don't have to
+ * be re-enabled.
+*/
+pub(crate) fn add_onstack_to_handlers() {
+    for signal in Signal::iterator() {
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            if libc::sigaction(signal as libc::c_int, std::ptr::null(), &mut action) == -1 {
+                continue;
+            }
+            if action.sa_sigaction == c_handler_address() && action.sa_flags & libc::SA_ONSTACK == 0
+            {
+                action.sa_flags |= libc::SA_ONSTACK;
+                libc::sigaction(signal as libc::c_int, &action, std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+/* True if the signal currently points at fn c_handler(), i.e. some Mediator
+ * has it enabled. Asks the kernel, so a change still queued for an Event Loop
+ * isn't seen.
+*/
+pub(crate) fn is_handled(signal: Signal) -> bool {
     unsafe {
-        sender = SENDER.as_ref().unwrap().clone();
+        let mut action = std::mem::zeroed::<libc::sigaction>();
+        libc::sigaction(signal as libc::c_int, std::ptr::null(), &mut action) == 0
+            && action.sa_sigaction == c_handler_address()
     }
+}
 
-    /* Drop the error since we can't return one from across the kernel
-     * boundary.
-    	*/
-    let _ = sender.send(Action::Call(signal));
+/* Used by the subsystems (e.g. Shutdown) that need the process to die the way
+ * it would have without Addy. Goes straight to the kernel instead of through
+ * the Event Loop since it is often called from a callback running on it.
+ *
+ * Restores SIG_DFL, unblocks the signal and raises it. If the default action
+ * doesn't terminate us we exit with the shell's 128 + signal convention.
+*/
+pub(crate) fn reset_and_raise(signal: Signal) -> ! {
+    let signum = signal as libc::c_int;
+    unsafe {
+        libc::signal(signum, libc::SIG_DFL);
+
+        let mut set = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(set.as_mut_ptr());
+        libc::sigaddset(set.as_mut_ptr(), signum);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, set.as_ptr(), std::ptr::null_mut());
+
+        libc::raise(signum);
+    }
+    std::process::exit(128 + signum)
 }
 
 /*****************
@@ -570,12 +972,124 @@ fn c_handler(signal: Signal, _info: CVoid, _ucontext: CVoid) {
 pub struct SignalHandle {
     signal: Signal,
     sender: Sender<Action>,
+    /* Added to the names of the callbacks registered through this handle */
+    namespace: String,
+    /* Given to the callbacks registered through this handle */
+    tags: Vec<String>,
+    /* Sends to an Event Loop driven by a Dispatcher, don't wait on it */
+    caller_driven: bool,
 }
 
 /* Convenient Type Alias */
 type SignalResult<'a> = Result<&'a mut SignalHandle, Error>;
 
+/* Turns an FnMut callback into the Fn the Event Loop stores. Only one call
+ * ever runs at a time so the lock is never contended, it's there so the
+ * callback only needs to be Send.
+*/
+fn exclusive<F>(cb: F) -> impl Fn(Signal) + Send
+where
+    F: FnMut(Signal) + Send + 'static,
+{
+    let cb = Mutex::new(cb);
+    move |signal: Signal| {
+        /* A previous call panicking doesn't make the callback unusable */
+        let mut cb = cb.lock().unwrap_or_else(|err| err.into_inner());
+        cb(signal)
+    }
+}
+
+/* Wraps a callback being registered so its runs are counted by addy::stats(),
+ * reported by the log/tracing features and its panics go to addy::on_error().
+*/
+fn observe<F>(signal: Signal, name: &str, cb: F) -> impl Fn(&SignalEvent) + Send
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    let cb = report::guard(signal, name, trace::instrument(signal, name, cb));
+    stats::instrument(signal, name, cb)
+}
+
 impl SignalHandle {
+    /// Puts the callbacks registered and removed through this handle in
+    /// their own namespace, so they can't clash with callbacks of the same
+    /// name from somewhere else. `addy::clear_namespace(...)` removes all of
+    /// them at once.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGTERM;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     /* Neither replaces the other */
+    ///     addy::mediate(SIGTERM)
+    ///             .namespace("mylib")
+    ///             .register("cleanup", |_signal| { println!("Library cleanup"); })?;
+    ///     addy::mediate(SIGTERM)
+    ///             .register("cleanup", |_signal| { println!("Application cleanup"); })?
+    ///             .enable()?;
+    ///
+    ///     //-- Later --//
+    ///     addy::clear_namespace("mylib")?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn namespace<A: AsRef<str>>(mut self, namespace: A) -> Self {
+        self.namespace = String::from(namespace.as_ref());
+        self
+    }
+
+    /// Tags the callbacks registered through this handle from now on, so
+    /// `addy::remove_tag(...)` can remove them all at once whatever signal
+    /// they're for. Can be called more than once to add more tags.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::{SIGHUP, SIGUSR1};
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGHUP)
+    ///             .tag("reload")
+    ///             .register("config", |_signal| { /* Reread the config */ })?
+    ///             .enable()?;
+    ///     addy::mediate(SIGUSR1)
+    ///             .tag("reload")
+    ///             .register("certs", |_signal| { /* Reread the certificates */ })?
+    ///             .enable()?;
+    ///
+    ///     //-- Later --//
+    ///     addy::remove_tag("reload")?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn tag<A: AsRef<str>>(mut self, tag: A) -> Self {
+        self.tags.push(String::from(tag.as_ref()));
+        self
+    }
+
+    /* Scopes a callback name to this handle's namespace */
+    fn name<A: AsRef<str>>(&self, name: A) -> Name {
+        Name {
+            namespace: self.namespace.clone(),
+            name: String::from(name.as_ref()),
+        }
+    }
+
+    /* Sends a callback to the Event Loop with this handle's tags */
+    fn add(&mut self, name: Name, cb: CBP, added: Option<Sender<bool>>) -> SignalResult<'_> {
+        let registration = Registration {
+            signal: self.signal,
+            name,
+            tags: self.tags.clone(),
+            cb,
+            added,
+        };
+        self.sender
+            .send(Action::Register(registration))
+            .map_err(|_| Error::CallFailed)?;
+        Ok(self)
+    }
+
     /// Registers a callback with the interrupt handler for the associated
     /// Signal. If you call register with the same name it will replace the
     /// previous callback.
@@ -597,18 +1111,259 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn register<'a, A, F>(&'a mut self, name: A, cb: F) -> SignalResult
+    pub fn register<A, F>(&mut self, name: A, cb: F) -> SignalResult<'_>
     where
         A: AsRef<str>,
-        F: Fn(Signal) -> () + Send + 'static,
+        F: Fn(Signal) + Send + 'static,
     {
         /* Box the Callback */
-        let cb = CBP(Box::new(cb));
-        let name = String::from(name.as_ref());
-        self.sender
-            .send(Action::Register(self.signal, name, cb))
-            .map_err(|_| Error::CallFailed)?;
-        Ok(self)
+        let name = self.name(name);
+        let cb = observe(
+            self.signal,
+            &name.to_string(),
+            move |event: &SignalEvent| cb(event.signal()),
+        );
+        self.add(name, CBP(Box::new(cb)), None)
+    }
+
+    /// Registers a callback like .register(), unless one with the same name
+    /// is already registered for the signal, in which case it returns
+    /// `Error::AlreadyRegistered` and leaves the existing one alone.
+    ///
+    /// It waits for the Event Loop, so it returns `Error::InCallback` if it's
+    /// called from a callback running on one, and `Error::CallerDriven` if
+    /// the Event Loop is driven by a `Dispatcher`.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGTERM;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGTERM).try_register("cleanup", |_signal| {})?;
+    ///
+    ///     let again = addy::mediate(SIGTERM).try_register("cleanup", |_signal| {}).map(|_| ());
+    ///     assert_eq!(again, Err(addy::Error::AlreadyRegistered));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn try_register<A, F>(&mut self, name: A, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: Fn(Signal) + Send + 'static,
+    {
+        if context::dispatching() {
+            return Err(Error::InCallback);
+        }
+        if self.caller_driven {
+            return Err(Error::CallerDriven);
+        }
+
+        let name = self.name(name);
+        let cb = observe(
+            self.signal,
+            &name.to_string(),
+            move |event: &SignalEvent| cb(event.signal()),
+        );
+        let (added, reply) = mpsc::channel();
+        self.add(name, CBP(Box::new(cb)), Some(added))?;
+        match reply.recv() {
+            Ok(true) => Ok(self),
+            Ok(false) => Err(Error::AlreadyRegistered),
+            Err(_) => Err(Error::CallFailed),
+        }
+    }
+
+    /// Registers a callback like .register() that gets the whole
+    /// `SignalEvent`, e.g. to find out who sent the signal.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGUSR1;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGUSR1)
+    ///             .register_event("who", |event| {
+    ///                 println!("{} from {:?}", event.signal(), event.pid());
+    ///             })?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_event<A, F>(&mut self, name: A, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: Fn(&SignalEvent) + Send + 'static,
+    {
+        let name = self.name(name);
+        let cb = observe(self.signal, &name.to_string(), cb);
+        self.add(name, CBP(Box::new(cb)), None)
+    }
+
+    /// Registers a callback like .register() that can fail. Errors it
+    /// returns are handed to the hook set with `addy::on_error(...)`.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGHUP;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGHUP)
+    ///             .register_fallible("reload", |_signal| {
+    ///                 let config = std::fs::read_to_string("config.toml")?;
+    ///                 println!("Reloaded {} bytes", config.len());
+    ///                 Ok::<(), std::io::Error>(())
+    ///             })?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_fallible<A, F, E>(&mut self, name: A, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: Fn(Signal) -> Result<(), E> + Send + 'static,
+        E: std::error::Error + Send + 'static,
+    {
+        let name = self.name(name);
+        let failing = name.to_string();
+        let cb = observe(
+            self.signal,
+            &name.to_string(),
+            move |event: &SignalEvent| {
+                if let Err(err) = cb(event.signal()) {
+                    report::failed(event.signal(), &failing, err);
+                }
+            },
+        );
+        self.add(name, CBP(Box::new(cb)), None)
+    }
+
+    /// Registers a callback like .register() that gets a `CallbackContext`,
+    /// to remove itself, change how the signal is handled, register more
+    /// callbacks or run another signal's callbacks. Changes are applied as
+    /// soon as the signal's callbacks are done, before the next signal.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGINT;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGINT)
+    ///             .register_context("first", |context| {
+    ///                 println!("Press Ctrl + C again to exit");
+    ///                 context.remove_self().default();
+    ///             })?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_context<A, F>(&mut self, name: A, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: Fn(&mut CallbackContext) + Send + 'static,
+    {
+        let name = self.name(name);
+        let own = name.clone();
+        let cb = observe(
+            self.signal,
+            &name.to_string(),
+            move |event: &SignalEvent| {
+                let mut context = CallbackContext::new(*event, own.clone());
+                cb(&mut context);
+                context.defer();
+            },
+        );
+        self.add(name, CBP(Box::new(cb)), None)
+    }
+
+    /// Registers a callback like .register(), choosing where it runs. Use it
+    /// to keep slow callbacks from holding up the Event Loop.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::{Execution, Overflow, SIGUSR1};
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGUSR1)
+    ///             .register_with(
+    ///                 "flush",
+    ///                 Execution::Dedicated { queue: 1, overflow: Overflow::DropNewest },
+    ///                 |_signal| { /* Takes a while */ },
+    ///             )?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_with<A, F>(&mut self, name: A, execution: Execution, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: Fn(Signal) + Send + 'static,
+    {
+        let name = self.name(name);
+        let cb = observe(
+            self.signal,
+            &name.to_string(),
+            move |event: &SignalEvent| cb(event.signal()),
+        );
+        self.add(name, CBP(execution::wrap(execution, cb)?), None)
+    }
+
+    /// Registers a callback like .register() that can keep state between
+    /// calls without a Mutex of its own.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGINT;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     let mut presses = 0;
+    ///     addy::mediate(SIGINT)
+    ///             .register_mut("count", move |_signal| {
+    ///                 presses += 1;
+    ///                 println!("Ctrl + C pressed {} times", presses);
+    ///             })?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_mut<A, F>(&mut self, name: A, cb: F) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: FnMut(Signal) + Send + 'static,
+    {
+        self.register(name, exclusive(cb))
+    }
+
+    /// Registers a callback like .register_mut(), choosing where it runs like
+    /// .register_with(). It still only runs one call at a time, even on a
+    /// `Pool`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use addy::{Execution, Overflow, Pool, SIGUSR1};
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     let mut log = std::io::BufWriter::new(std::io::stderr());
+    ///     let pool = Pool::new(2, 16, Overflow::DropOldest)?;
+    ///     addy::mediate(SIGUSR1)
+    ///             .register_mut_with("log", Execution::Pool(pool), move |signal| {
+    ///                 let _ = writeln!(log, "Got {}", signal);
+    ///             })?
+    ///             .enable()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn register_mut_with<A, F>(
+        &mut self,
+        name: A,
+        execution: Execution,
+        cb: F,
+    ) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+        F: FnMut(Signal) + Send + 'static,
+    {
+        self.register_with(name, execution, exclusive(cb))
     }
 
     /// Removes a named callback from the associated Signal. If no callback with
@@ -636,17 +1391,31 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn remove<'a, A>(&'a mut self, name: A) -> SignalResult
+    pub fn remove<A>(&mut self, name: A) -> SignalResult<'_>
     where
         A: AsRef<str>,
     {
-        let name = String::from(name.as_ref());
+        let name = self.name(name);
         self.sender
             .send(Action::Remove(self.signal, name))
             .map_err(|_| Error::CallFailed)?;
         Ok(self)
     }
 
+    /* Like .remove(...), then puts the default behaviour back if no
+     * callbacks are left
+    	*/
+    pub(crate) fn retire<A>(&mut self, name: A) -> SignalResult<'_>
+    where
+        A: AsRef<str>,
+    {
+        let name = self.name(name);
+        self.sender
+            .send(Action::Retire(self.signal, name))
+            .map_err(|_| Error::CallFailed)?;
+        Ok(self)
+    }
+
     /// Removes a all callbacks from the associated Signal. Functionally similar
     /// to calling .ignore() except you don't need to call .enable() if you add
     /// new callbacks later.
@@ -675,7 +1444,7 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn clear<'a>(&'a mut self) -> SignalResult {
+    pub fn clear(&mut self) -> SignalResult<'_> {
         self.sender
             .send(Action::Clear(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -712,7 +1481,7 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn release<'a>(&'a mut self) -> SignalResult {
+    pub fn release(&mut self) -> SignalResult<'_> {
         self.sender
             .send(Action::Release(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -744,7 +1513,7 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn ignore<'a>(&'a mut self) -> SignalResult {
+    pub fn ignore(&mut self) -> SignalResult<'_> {
         self.sender
             .send(Action::Ignore(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -777,7 +1546,7 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn default<'a>(&'a mut self) -> SignalResult {
+    pub fn default(&mut self) -> SignalResult<'_> {
         self.sender
             .send(Action::Default(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -811,7 +1580,7 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn resume<'a>(&'a mut self) -> SignalResult {
+    pub fn resume(&mut self) -> SignalResult<'_> {
         self.sender
             .send(Action::Resume(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -834,7 +1603,33 @@ impl SignalHandle {
     ///		Ok(())
     /// }
     /// ```
-    pub fn enable<'a>(&'a mut self) -> SignalResult {
+    pub fn enable(&mut self) -> SignalResult<'_> {
+        self.sender
+            .send(Action::Resume(self.signal))
+            .map_err(|_| Error::CallFailed)?;
+        Ok(self)
+    }
+
+    /// Begins capturing the interrupt like .enable(), using the given
+    /// sigaction(...) flags and mask. The options are kept, so later calls
+    /// to .resume() reinstall the handler the same way. If the interrupt is
+    /// already being captured the new options take effect immediately.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::{HandlerOptions, SIGINT};
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     addy::mediate(SIGINT)
+    ///             .register("print", |_signal| { println!("Interrupted!"); })?
+    ///             .enable_with(HandlerOptions::new().restart(true))?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn enable_with(&mut self, options: HandlerOptions) -> SignalResult<'_> {
+        self.sender
+            .send(Action::Options(self.signal, options))
+            .map_err(|_| Error::CallFailed)?;
         self.sender
             .send(Action::Resume(self.signal))
             .map_err(|_| Error::CallFailed)?;
@@ -855,13 +1650,16 @@ impl SignalHandle {
 */
 static SETUP: Once = Once::new();
 
+/* Set once a Dispatcher drives the default Event Loop. Nothing answers a
+ * reply channel until it's polled, maybe by the thread that's waiting.
+*/
+static CALLER_DRIVEN: AtomicBool = AtomicBool::new(false);
+
 /* FUTURE: Consider removing this to remove the dependency on lazy_static!()
- * This gets set up ONCE and then only read from. The downside is more
- * unsafe {} blocks :<
+ * This gets set up ONCE and then only read from.
  *
- * Currently SENDER is only accessed in one place, that can only be run one at
- * a time (i.e. in an interrupt) and copies of SAFE_SENDER can be made from
- * any thread at any time. Still... it's read only...
+ * The handlers use the copy in kernel::SLOTS, copies of SAFE_SENDER can be
+ * made from any thread at any time. Still... it's read only...
 */
 lazy_static! {
     /* MPSC channel used by interrupts to communicate to the Event Loop. This
@@ -873,22 +1671,28 @@ lazy_static! {
     };
 }
 
-/* C FFI MESSAGE PASSER
- *
- * Copy of a sender to the Event Loop. It is only setup ONCE on the first
- * addy::mediate() call. The setup always occurs before it is READ from as it is
- * set before any handler is registered (the only place that attempts to read
- * from this static global).
-*/
-static mut SENDER: Option<Sender<Action>> = None;
+/* Where the Event Loop runs */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Driver {
+    /* On a thread Addy spawns, the default */
+    Thread,
+    /* On whichever thread drives the Dispatcher */
+    Caller,
+}
 
 /* This is the initial Addy setup. It sets up the Event Loop and the MPCS
  * channel. Setup occurs on the first call of addy::mediate(Signal).
 */
-
-type NameToCallback = FnvHashMap<String, CBP>;
-type SignalToCallbacks<T> = FnvHashMap<Signal, T>;
 fn setup() {
+    start(Driver::Thread);
+}
+
+/* Sets up the MPSC channel and the Event Loop. Returns the Event Loop if
+ * this call set it up and the caller is going to drive it, None otherwise.
+*/
+fn start(driver: Driver) -> Option<EventLoop> {
+    let mut caller_driven = None;
+
     /* Only setup the Event Loop once */
     SETUP.call_once(|| {
         // we may need to block on "completed" to make sure this is completed
@@ -903,194 +1707,20 @@ fn setup() {
             guard.replace(sender.clone());
         }
 
-        /* Save a copy of the sender in an global static mut Option
-         *
-         * This is SAFE because this is only called ONCE and the only other
-         * place this is accessed is in fn  c_handler() which cannot be called
-         * before this setup is run. In addition, only one interrupt handler can
-         * be running at a time, which is why this convolution is necessary.
-        	*/
-        unsafe {
-            SENDER.replace(sender.clone());
-        }
+        /* Give the handlers their own copy of the sender */
+        kernel::attach_default(sender.clone());
 
-        /**************
-         * EVENT LOOP *
-         **************/
-
-        /* Spawn the Event Loop thread, pass the receiver to it. */
-        thread::spawn(move || {
-            /* Create a map from Signal -> Map<Name, Closure> */
-            let nsig = usize::try_from(NUM_SIGNALS).unwrap(); // i32(32) - constant we control :)
-            let mut handlers = SignalToCallbacks::<NameToCallback>::with_capacity_and_hasher(
-                nsig,
-                Default::default(),
-            );
-
-            /* Stores if we need to re-establish fn c_handler() as the interrupt
-             * handler. e.g. if the user called .ignore() and then .resume()
-            	*/
-            let mut active: [bool; NUM_SIGNALS as usize] = [false; 32];
-
-            /*************
-             * CONSTANTS *
-             *************/
-            /* SigAction Structs to represent the SIG_DFL, SIG_IGN and custom
-             * handler. These are passed to libc::sigaction(...) to tell it what
-             * to do when a signal is called. They tell it to perform the
-             * default action, ignore the signal or run the list of user
-             * registered callbacks respectively.
-             */
-
-            /* Have to create a mask for the structs that enables all singals */
-            let mut sigset = std::mem::MaybeUninit::uninit();
-            let _ = unsafe { libc::sigfillset(sigset.as_mut_ptr()) };
-            let sigset = unsafe { sigset.assume_init() };
-
-            #[allow(non_snake_case)]
-            let SA_DEFAULT: libc::sigaction = libc::sigaction {
-                sa_sigaction: libc::SIG_DFL,
-                sa_mask: sigset,
-                sa_flags: libc::SA_SIGINFO,
-                #[cfg(target_os = "linux")]
-                sa_restorer: None,
-            };
-
-            #[allow(non_snake_case)]
-            let SA_IGNORE: libc::sigaction = libc::sigaction {
-                sa_sigaction: libc::SIG_IGN,
-                sa_mask: sigset,
-                sa_flags: libc::SA_SIGINFO,
-                #[cfg(target_os = "linux")]
-                sa_restorer: None,
-            };
-
-            /* Q: Why isn't this a constant?
-             * A: Converting function pointers to integers in a constant is
-             * unstable. (Yes I tried the various workarounds)
-             *
-             * Link: https://github.com/rust-lang/rust/issues/51910
-             */
-            #[allow(non_snake_case)]
-            let SA_CALLBACK: libc::sigaction = libc::sigaction {
-                sa_sigaction: c_handler as libc::sighandler_t,
-                sa_mask: sigset,
-                sa_flags: libc::SA_SIGINFO,
-                #[cfg(target_os = "linux")]
-                sa_restorer: None,
-            };
-
-            /***************************************
-             * HELPER FUNCTIONS TO KEEP THINGS DRY *
-             ***************************************/
-            /* Switched to helper closures because some architectures need
-             * a proper sa_mask generated to compile.
-             */
-
-            /* Tells the process to ignore the interrupt */
-            let ignore = move |signal: Signal| unsafe {
-                libc::sigaction(signal as libc::c_int, &SA_IGNORE, std::ptr::null_mut());
-            };
-            /* Sets the interrupt handler to the default value */
-            let default = move |signal: Signal| unsafe {
-                libc::sigaction(signal as libc::c_int, &SA_DEFAULT, std::ptr::null_mut());
-            };
-            /* Trys to convert a Signal to a USize to index into active[] */
-            fn index(signal: Signal) -> usize {
-                usize::try_from(signal as libc::c_int).unwrap()
+        let mut event_loop = EventLoop::new(receiver);
+        match driver {
+            /* Spawn the Event Loop thread, pass the receiver to it. */
+            Driver::Thread => {
+                thread::spawn(move || event_loop.run());
             }
-            /* Resets all signals to their default behaviour. Does not clear out
-             * registered handlers.
-            	*/
-            let set_all_to_default = || {
-                for signal in Signal::iterator() {
-                    default(signal);
-                }
-            };
-
-            /*********
-             * PANIC *
-             *********/
-
-            /* If this thread panics for any reason, set all signals to the
-             * default behavior.
-            	*/
-            let _ = std::panic::catch_unwind(|| {
-                set_all_to_default();
-            });
-
-            /**************
-             * EVENT LOOP *
-             **************/
-
-            /* Returns None when the channel is closed. */
-            let mut messages = receiver.iter();
-            while let Some(action) = messages.next() {
-                match action {
-                    Action::Call(signal) => {
-                        /* Get the map of callbacks for this signal */
-                        if let Some(callbacks) = handlers.get(&signal) {
-                            /* Call each callback */
-                            let callbacks = callbacks.iter();
-                            for (_, cb) in callbacks {
-                                cb.0(signal);
-                            }
-                        }
-                    }
-                    Action::Register(signal, name, cb) => {
-                        /* Get the map of callbacks for this signal */
-                        let callbacks = handlers.entry(signal).or_default();
-                        callbacks.insert(name, cb);
-                    }
-                    Action::Remove(signal, name) => {
-                        /* Get the map of callbacks for this signal */
-                        if let Some(callbacks) = handlers.get_mut(&signal) {
-                            callbacks.remove(&name);
-                        }
-                    }
-                    Action::Clear(signal) => {
-                        handlers.remove(&signal);
-                    }
-                    Action::Ignore(signal) => {
-                        ignore(signal);
-                        active[index(signal)] = false;
-                    }
-                    Action::Default(signal) => {
-                        default(signal);
-                        active[index(signal)] = false;
-                    }
-                    Action::Release(signal) => {
-                        /* Clear the callback map */
-                        handlers.remove(&signal);
-
-                        /* Set the handler back to the defaults */
-                        default(signal);
-                        active[index(signal)] = false;
-                    }
-                    Action::Resume(signal) => {
-                        /* Check to see if it's already setup up */
-                        if !active[index(signal)] {
-                            unsafe {
-                                /* SA_CALLBACK is a static sigaction struct that
-                                 * points to c_handler(...)
-                                	*/
-                                libc::sigaction(
-                                    signal as libc::c_int,
-                                    &SA_CALLBACK,
-                                    std::ptr::null_mut(),
-                                );
-                            }
-                            active[index(signal)] = true;
-                        }
-                    }
-                }
-            } // </Event Loop>
-
-            /* If the thread closes - set all the singals back to their default
-             * behavior and remove all callbacks.
-             */
-            set_all_to_default();
-        }); // </Thread>
+            Driver::Caller => {
+                CALLER_DRIVEN.store(true, Ordering::SeqCst);
+                caller_driven = Some(event_loop);
+            }
+        }
     }); // </Once>
 
     /* There's a chance that the ONCE call actually initialized something else
@@ -1103,6 +1733,268 @@ fn setup() {
      */
     #[cfg(feature = "nightly")]
     while !SETUP.is_completed() { /*-- ᓚᘏᗢ --*/ }
+
+    caller_driven
+}
+
+/**************
+ * EVENT LOOP *
+ **************/
+
+/* A registered callback and the tags it was registered with */
+struct Callback {
+    cb: CBP,
+    tags: Vec<String>,
+}
+
+type NameToCallback = FnvHashMap<Name, Callback>;
+type SignalToCallbacks<T> = FnvHashMap<Signal, T>;
+
+/* Everything the Event Loop owns. It either runs on its own thread or is
+ * driven by a Dispatcher.
+*/
+struct EventLoop {
+    receiver: Receiver<Action>,
+    /* Map from Signal -> Map<Name, Closure> */
+    handlers: SignalToCallbacks<NameToCallback>,
+    /* Stores if we need to re-establish fn c_handler() as the interrupt
+     * handler. e.g. if the user called .ignore() and then .resume()
+    	*/
+    active: [bool; NUM_SIGNALS as usize],
+    /* The sigaction(...) flags and mask to use for each interrupt */
+    options: [HandlerOptions; NUM_SIGNALS as usize],
+}
+
+/* Trys to convert a Signal to a USize to index into active[] */
+fn index(signal: Signal) -> usize {
+    usize::try_from(signal as libc::c_int).unwrap()
+}
+
+impl EventLoop {
+    fn new(receiver: Receiver<Action>) -> Self {
+        let nsig = usize::try_from(NUM_SIGNALS).unwrap(); // i32(32) - constant we control :)
+        EventLoop {
+            receiver,
+            handlers: SignalToCallbacks::with_capacity_and_hasher(nsig, Default::default()),
+            active: [false; NUM_SIGNALS as usize],
+            options: [HandlerOptions::default(); NUM_SIGNALS as usize],
+        }
+    }
+
+    /* Returns when the channel is closed. */
+    fn run(&mut self) {
+        while let Ok(action) = self.receiver.recv() {
+            self.handle(action);
+        }
+    }
+
+    fn handle(&mut self, action: Action) {
+        trace::action(&action);
+        match action {
+            Action::Call(event) => {
+                let signal = event.signal();
+                stats::dequeued(signal);
+
+                /* Another Mediator is handling it, this one isn't */
+                if !self.active[index(signal)] {
+                    return;
+                }
+
+                /* SA_RESETHAND put SIG_DFL back, .resume() has to
+                 * reinstall the handler.
+                	*/
+                if self.options[index(signal)].resets() {
+                    self.active[index(signal)] = false;
+                    kernel::forget(signal);
+                }
+
+                let start = Instant::now();
+                self.dispatch(&event, |_| {});
+                trace::called(&event, start.elapsed());
+                self.apply_deferred();
+            }
+            #[cfg(feature = "testing")]
+            Action::Inject(event, reply) => {
+                let mut ran = Vec::new();
+                self.dispatch(&event, |name| ran.push(name.to_string()));
+                self.apply_deferred();
+                let _ = reply.send(ran);
+            }
+            Action::Register(registration) => {
+                /* Get the map of callbacks for this signal */
+                let callbacks = self.handlers.entry(registration.signal).or_default();
+                let callback = Callback {
+                    cb: registration.cb,
+                    tags: registration.tags,
+                };
+                match registration.added {
+                    Some(added) => {
+                        /* .try_register() never replaces */
+                        let free = !callbacks.contains_key(&registration.name);
+                        if free {
+                            callbacks.insert(registration.name, callback);
+                        }
+                        let _ = added.send(free);
+                    }
+                    None => {
+                        callbacks.insert(registration.name, callback);
+                    }
+                }
+            }
+            Action::Remove(signal, name) => {
+                /* Get the map of callbacks for this signal */
+                if let Some(callbacks) = self.handlers.get_mut(&signal) {
+                    callbacks.remove(&name);
+                }
+            }
+            Action::Retire(signal, name) => {
+                let empty = match self.handlers.get_mut(&signal) {
+                    Some(callbacks) => {
+                        callbacks.remove(&name);
+                        callbacks.is_empty()
+                    }
+                    None => true,
+                };
+                if empty {
+                    self.handlers.remove(&signal);
+                    self.release(signal, kernel::default);
+                }
+            }
+            Action::RemoveTag(tag) => {
+                for callbacks in self.handlers.values_mut() {
+                    callbacks.retain(|_, callback| !callback.tags.contains(&tag));
+                }
+            }
+            Action::ClearNamespace(namespace) => {
+                for callbacks in self.handlers.values_mut() {
+                    callbacks.retain(|name, _| name.namespace != namespace);
+                }
+            }
+            Action::Clear(signal) => {
+                self.handlers.remove(&signal);
+            }
+            Action::Ignore(signal) => {
+                self.release(signal, kernel::ignore);
+            }
+            Action::Default(signal) => {
+                self.release(signal, kernel::default);
+            }
+            Action::Release(signal) => {
+                /* Clear the callback map */
+                self.handlers.remove(&signal);
+
+                /* Set the handler back to the defaults */
+                self.release(signal, kernel::default);
+            }
+            Action::Resume(signal) => {
+                /* Check to see if it's already setup up */
+                if !self.active[index(signal)] {
+                    kernel::claim(signal, &self.options[index(signal)]);
+                    self.active[index(signal)] = true;
+                }
+            }
+            Action::Options(signal, new_options) => {
+                self.options[index(signal)] = new_options;
+
+                /* Already handling it - swap the options in now */
+                if self.active[index(signal)] {
+                    kernel::reinstall(signal, &new_options);
+                }
+            }
+            #[cfg(feature = "serde")]
+            Action::Batch(actions) => {
+                for action in actions {
+                    self.handle(action);
+                }
+            }
+            Action::Run(job) => {
+                (job.0)();
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Action::Timer(id) => {
+                /* Already reported, like a panic in dispatch(...) */
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| timer::dispatch(id)));
+            }
+        }
+    }
+
+    /* Calls every callback for the event's signal, telling ran(...) the name
+     * of each one first.
+    	*/
+    fn dispatch<F: FnMut(&Name)>(&self, event: &SignalEvent, mut ran: F) {
+        let _dispatching = context::Dispatching::enter();
+
+        /* Get the map of callbacks for this signal */
+        if let Some(callbacks) = self.handlers.get(&event.signal()) {
+            /* Call each callback */
+            let callbacks = callbacks.iter();
+            for (name, callback) in callbacks {
+                ran(name);
+                /* A panic has already been reported to addy::on_error(...),
+                 * it mustn't take the Event Loop (and every signal) with it
+                	*/
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| callback.cb.0(event)));
+            }
+        }
+    }
+
+    /* Applies what callbacks asked for through their CallbackContext. A
+     * triggered signal's callbacks can ask for more, so keep going until
+     * they stop or the triggers get too deep.
+    	*/
+    fn apply_deferred(&mut self) {
+        let mut depth = 0;
+        loop {
+            let actions = context::take();
+            if actions.is_empty() {
+                return;
+            }
+            depth += 1;
+            for action in actions {
+                match action {
+                    Action::Call(event) if depth > context::MAX_TRIGGER_DEPTH => {
+                        report::trigger_depth(event.signal(), context::MAX_TRIGGER_DEPTH);
+                    }
+                    Action::Call(event) => {
+                        if self.active[index(event.signal())] {
+                            let start = Instant::now();
+                            self.dispatch(&event, |_| {});
+                            trace::called(&event, start.elapsed());
+                        }
+                    }
+                    action => self.handle(action),
+                }
+            }
+        }
+    }
+
+    /* Stops handling the signal. The disposition only reaches the kernel
+     * if no other Mediator is still handling it.
+    	*/
+    fn release(&mut self, signal: Signal, disposition: fn(Signal)) {
+        kernel::release(signal, self.active[index(signal)], disposition);
+        self.active[index(signal)] = false;
+    }
+}
+
+/*********
+ * PANIC *
+ *********/
+
+/* If the Event Loop panics or closes for any reason, set the signals it was
+ * handling back to the default behavior, unless another Mediator is still
+ * handling them. This used to be a catch_unwind(...) which ran immediately
+ * and clobbered handlers installed outside of the Event Loop (e.g.
+ * addy::crash).
+*/
+impl Drop for EventLoop {
+    fn drop(&mut self) {
+        for signal in Signal::iterator() {
+            if self.active[index(signal)] {
+                self.release(signal, kernel::default);
+            }
+        }
+    }
 }
 
 /***********
@@ -1137,21 +2029,33 @@ fn setup() {
 /// }
 /// ```
 pub fn mediate<S: Into<Signal>>(signal: S) -> SignalHandle {
-    let signal = signal.into();
+    Mediator::global().mediate(signal)
+}
 
-    /* Performs the initial setup for all handlers - only called ONCE */
-    setup();
+/// Removes every callback registered with `tag`, whatever signal it's for.
+/// See `SignalHandle::tag(...)`.
+pub fn remove_tag<A: AsRef<str>>(tag: A) -> Result<(), Error> {
+    Mediator::global().remove_tag(tag)
+}
 
-    /* Create a clone() of the Sender so we can pass messages to the Event
-     * Loop from the returned struct.
-    	*/
-    let sender;
-    {
-        let guard = SAFE_SENDER.lock().unwrap();
-        sender = guard.as_ref().unwrap().clone();
-    }
+/// Removes every callback registered in `namespace`, whatever signal it's
+/// for. See `SignalHandle::namespace(...)`.
+pub fn clear_namespace<A: AsRef<str>>(namespace: A) -> Result<(), Error> {
+    Mediator::global().clear_namespace(namespace)
+}
 
-    SignalHandle { signal, sender }
+/* Runs job on the Event Loop after everything already sent to it. Used when
+ * something must not happen before earlier .register()/.enable() calls have
+ * taken effect, e.g. arming a timer.
+*/
+fn run_on_loop<F: FnOnce() + Send + 'static>(job: F) -> Result<(), Error> {
+    setup();
+    let guard = SAFE_SENDER.lock().unwrap();
+    guard
+        .as_ref()
+        .unwrap()
+        .send(Action::Run(Job(Box::new(job))))
+        .map_err(|_| Error::CallFailed)
 }
 
 /* Alternative, arcane, profane function aliases for addy::mediate(...) */
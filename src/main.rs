@@ -0,0 +1,225 @@
+//! # addy
+//! Sends and inspects signals using Addy's own signal names and metadata.
+//!
+//! ```text
+//! addy send <pid|name> <SIG> [--value <n>]
+//! addy list
+//! addy status <pid>
+//! addy wait <SIG>...
+//! ```
+use std::io::Write;
+use std::process::exit;
+use std::sync::mpsc;
+
+use addy::{Signal, SIGKILL, SIGSTOP};
+
+const USAGE: &str = "\
+Usage:
+    addy send <pid|name> <SIG> [--value <n>]   Send a signal, queued with a value if given
+    addy list                                  List the signals this platform has
+    addy status <pid>                          Show a process's blocked, ignored, caught and pending signals
+    addy wait <SIG>...                         Wait for one of the signals to arrive";
+
+/* Prints the error and exits like most command line tools do */
+fn fail<T: std::fmt::Display>(err: T) -> ! {
+    eprintln!("addy: {}", err);
+    exit(1)
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(2)
+}
+
+/* Stops quietly if stdout goes away, like when piped into head */
+fn output<I: IntoIterator<Item = String>>(lines: I) {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for line in lines {
+        if writeln!(out, "{}", line).is_err() {
+            return;
+        }
+    }
+}
+
+fn parse_signal(name: &str) -> Signal {
+    name.parse()
+        .unwrap_or_else(|_| fail(format!("unknown signal: {}", name)))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["send", target, signal] => send(target, parse_signal(signal), None),
+        ["send", target, signal, "--value", value] => {
+            let value = value
+                .parse()
+                .unwrap_or_else(|_| fail(format!("not a value: {}", value)));
+            send(target, parse_signal(signal), Some(value))
+        }
+        ["list"] => list(),
+        ["status", pid] => status(pid),
+        ["wait", signals @ ..] if !signals.is_empty() => {
+            wait(signals.iter().map(|name| parse_signal(name)).collect())
+        }
+        ["help"] | ["--help"] | ["-h"] => println!("{}", USAGE),
+        _ => usage(),
+    }
+}
+
+/********
+ * SEND *
+ ********/
+
+/* A pid, or every process whose name is target */
+fn pids(target: &str) -> Vec<libc::pid_t> {
+    if let Ok(pid) = target.parse() {
+        return vec![pid];
+    }
+    let pids = named(target);
+    if pids.is_empty() {
+        fail(format!("no process named {}", target));
+    }
+    pids
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn named(name: &str) -> Vec<libc::pid_t> {
+    let entries = std::fs::read_dir("/proc").unwrap_or_else(|err| fail(err));
+    let mut pids: Vec<libc::pid_t> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|pid| {
+            std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                .map(|comm| comm.trim_end() == name)
+                .unwrap_or(false)
+        })
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn named(_name: &str) -> Vec<libc::pid_t> {
+    fail("finding processes by name needs /proc, use a pid")
+}
+
+fn send(target: &str, signal: Signal, value: Option<usize>) {
+    for pid in pids(target) {
+        let sent = match value {
+            None => addy::send(pid, signal),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(value) => addy::queue(pid, signal, value),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Some(_) => fail("sigqueue(3) isn't available on this platform"),
+        };
+        if let Err(err) = sent {
+            fail(format!("{}: {}", pid, err));
+        }
+    }
+}
+
+/********
+ * LIST *
+ ********/
+
+fn list() {
+    let mut signals: Vec<Signal> = Signal::iterator().collect();
+    signals.sort_by_key(|signal| *signal as libc::c_int);
+    let header = format!("{:>3}  {:<10} {:<6} DESCRIPTION", "NUM", "NAME", "ACTION");
+    output(
+        std::iter::once(header).chain(signals.into_iter().map(|signal| {
+            format!(
+                "{:>3}  {:<10} {:<6} {}",
+                signal as libc::c_int,
+                signal.as_str(),
+                signal.default_action().as_str(),
+                signal.description()
+            )
+        })),
+    );
+}
+
+/**********
+ * STATUS *
+ **********/
+
+/* "-" reads better than nothing in a column */
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn names(set: addy::inspect::SignalSet) -> String {
+    if set.is_empty() {
+        String::from("-")
+    } else {
+        set.to_string()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn status(pid: &str) {
+    let pid: libc::pid_t = pid
+        .parse()
+        .unwrap_or_else(|_| fail(format!("not a pid: {}", pid)));
+    let signals =
+        addy::inspect::process(pid).unwrap_or_else(|err| fail(format!("{}: {}", pid, err)));
+
+    let process = vec![
+        ("Pending:", signals.pending()),
+        ("Blocked:", signals.blocked()),
+        ("Ignored:", signals.ignored()),
+        ("Caught:", signals.caught()),
+    ];
+    let process = process
+        .into_iter()
+        .map(|(label, set)| format!("{:<18} {}", label, names(set)));
+    let threads = signals.threads().iter().map(|thread| {
+        format!(
+            "{:<18} blocked {} pending {}",
+            format!("Thread {}:", thread.tid()),
+            names(thread.blocked()),
+            names(thread.pending())
+        )
+    });
+    output(process.chain(threads));
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn status(_pid: &str) {
+    fail("status needs /proc, which this platform doesn't have")
+}
+
+/********
+ * WAIT *
+ ********/
+
+/* Handles the signals with Addy until the first one arrives, then prints it
+ * and who sent it.
+*/
+fn wait(signals: Vec<Signal>) {
+    /* The kernel won't let these be handled, we'd wait forever */
+    if let Some(signal) = signals
+        .iter()
+        .find(|signal| **signal == SIGKILL || **signal == SIGSTOP)
+    {
+        fail(format!(
+            "{} can't be caught, so it can't be waited for",
+            signal
+        ));
+    }
+
+    let (arrived, arrival) = mpsc::channel();
+    for signal in signals {
+        let arrived = std::sync::Mutex::new(arrived.clone());
+        addy::mediate(signal)
+            .register_event("wait", move |event| {
+                let _ = arrived.lock().unwrap().send(*event);
+            })
+            .and_then(|handle| handle.enable())
+            .unwrap_or_else(|err| fail(format!("{}: {}", signal, err)));
+    }
+
+    let event = arrival.recv().unwrap_or_else(|err| fail(err));
+    match event.pid() {
+        Some(pid) => println!("{} from {}", event.signal(), pid),
+        None => println!("{}", event.signal()),
+    }
+}
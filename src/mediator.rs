@@ -0,0 +1,161 @@
+/* Independent sets of callbacks.
+ *
+ * addy::mediate(...) goes through one process wide default Mediator, so every
+ * library in the process shares its callback names. A Mediator made with
+ * Mediator::new() has its own callback tables and its own Event Loop. The
+ * handlers are still process wide, kernel::broadcast() hands each signal to
+ * every live Mediator.
+*/
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::{kernel, Action, Error, EventLoop, Signal, SignalHandle};
+
+/// An independent set of callbacks with its own Event Loop thread.
+/// `addy::mediate(...)` is the same as `Mediator::global().mediate(...)`.
+///
+/// Every Mediator sees every signal that any Mediator is handling, but only
+/// runs its callbacks for the signals it has enabled itself. A signal goes
+/// back to its default (or ignored) behaviour once no Mediator is handling
+/// it any more.
+///
+/// Cloning is cheap and gives another handle to the same Mediator. Once
+/// every clone has been dropped no more signals reach it, even if
+/// `SignalHandle`s it returned are still alive. Its Event Loop keeps
+/// running until those are dropped too, then exits and stops handling its
+/// signals.
+///
+/// # Example
+/// ```
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+/// use addy::{Mediator, SIGUSR1};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let (tx, rx) = mpsc::channel();
+///
+///     /* Two libraries, both with a "cleanup" callback */
+///     let ours = Mediator::new()?;
+///     let theirs = Mediator::new()?;
+///     for (mediator, who) in vec![(&ours, "ours"), (&theirs, "theirs")] {
+///         let tx = tx.clone();
+///         mediator.mediate(SIGUSR1)
+///                 .register("cleanup", move |_signal| { let _ = tx.send(who); })?
+///                 .enable()?;
+///     }
+///
+///     /* Give both Event Loops a moment to install the handler */
+///     std::thread::sleep(Duration::from_millis(100));
+///     SIGUSR1.raise()?;
+///
+///     let mut ran = vec![rx.recv().unwrap(), rx.recv().unwrap()];
+///     ran.sort();
+///     assert_eq!(ran, vec!["ours", "theirs"]);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Mediator {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    sender: Mutex<mpsc::Sender<Action>>,
+    /* Where the handlers find our sender. None for the default Mediator,
+     * which keeps its slot forever.
+    	*/
+    slot: Option<usize>,
+}
+
+/* The slot holds a sender too, so the Event Loop's channel can't close
+ * while it's attached. Detach with the last clone instead, the Event Loop
+ * exits once the SignalHandles are gone as well.
+*/
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            #[cfg(feature = "serde")]
+            crate::policy::forget(slot);
+            kernel::detach(slot);
+        }
+    }
+}
+
+impl Mediator {
+    /// Creates a Mediator with its own Event Loop thread. Returns
+    /// `Error::TooManyMediators` if 63 are already alive.
+    pub fn new() -> Result<Mediator, Error> {
+        let (sender, receiver) = mpsc::channel::<Action>();
+        let slot = kernel::attach(sender.clone()).ok_or(Error::TooManyMediators)?;
+        thread::spawn(move || EventLoop::new(receiver).run());
+
+        Ok(Mediator {
+            inner: Arc::new(Inner {
+                sender: Mutex::new(sender),
+                slot: Some(slot),
+            }),
+        })
+    }
+
+    /// The default Mediator, the one behind `addy::mediate(...)`. The first
+    /// call sets up its Event Loop.
+    pub fn global() -> Mediator {
+        /* Performs the initial setup for all handlers - only called ONCE */
+        crate::setup();
+
+        let sender = crate::SAFE_SENDER.lock().unwrap().as_ref().unwrap().clone();
+        Mediator {
+            inner: Arc::new(Inner {
+                sender: Mutex::new(sender),
+                slot: None,
+            }),
+        }
+    }
+
+    /// Use this to get a SignalHandle for this Mediator representing the
+    /// interrupt specified by Signal. See `addy::mediate(...)`.
+    pub fn mediate<S: Into<Signal>>(&self, signal: S) -> SignalHandle {
+        /* Create a clone() of the Sender so we can pass messages to the Event
+         * Loop from the returned struct.
+        	*/
+        let sender = self.inner.sender.lock().unwrap().clone();
+        SignalHandle {
+            signal: signal.into(),
+            sender,
+            namespace: String::new(),
+            tags: Vec::new(),
+            caller_driven: self.inner.slot.is_none() && crate::CALLER_DRIVEN.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Removes every callback of this Mediator registered with `tag`. See
+    /// `addy::remove_tag(...)`.
+    pub fn remove_tag<A: AsRef<str>>(&self, tag: A) -> Result<(), Error> {
+        self.send(Action::RemoveTag(String::from(tag.as_ref())))
+    }
+
+    /// Removes every callback of this Mediator registered in `namespace`. See
+    /// `addy::clear_namespace(...)`.
+    pub fn clear_namespace<A: AsRef<str>>(&self, namespace: A) -> Result<(), Error> {
+        self.send(Action::ClearNamespace(String::from(namespace.as_ref())))
+    }
+
+    /* Which Mediator this is, 0 for the default one. Only unique among the
+     * live ones.
+    	*/
+    #[cfg(feature = "serde")]
+    pub(crate) fn slot(&self) -> usize {
+        self.inner.slot.unwrap_or(0)
+    }
+
+    pub(crate) fn send(&self, action: Action) -> Result<(), Error> {
+        self.inner
+            .sender
+            .lock()
+            .unwrap()
+            .send(action)
+            .map_err(|_| Error::CallFailed)
+    }
+}
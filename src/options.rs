@@ -0,0 +1,134 @@
+/* Per signal sigaction(...) configuration.
+ *
+ * Out of the box Addy installs fn c_handler() with SA_SIGINFO and every signal
+ * blocked while it runs. HandlerOptions lets callers pick the other sa_flags
+ * and the sa_mask. The Event Loop keeps the options per signal so .resume()
+ * reinstalls the handler exactly as it was configured.
+*/
+use crate::Signal;
+
+/// Options for the handler Addy installs for a signal, passed to
+/// `SignalHandle::enable_with(...)`. Everything defaults to off and the mask
+/// defaults to blocking every signal while the handler runs.
+///
+/// # Example
+/// ```
+/// use addy::{HandlerOptions, SIGUSR1, SIGUSR2};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     /* Don't make blocking reads return EINTR */
+///     let options = HandlerOptions::new()
+///             .restart(true)
+///             .mask(&[SIGUSR2]);
+///
+///     addy::mediate(SIGUSR1)
+///             .register("print", |_signal| { println!("Got SIGUSR1"); })?
+///             .enable_with(options)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct HandlerOptions {
+    flags: libc::c_int,
+    /* Bit n set means signal n is blocked. None blocks every signal */
+    mask: Option<u64>,
+}
+
+impl HandlerOptions {
+    /// Same as `HandlerOptions::default()`.
+    pub fn new() -> Self {
+        HandlerOptions::default()
+    }
+
+    fn flag(mut self, flag: libc::c_int, on: bool) -> Self {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+        self
+    }
+
+    /// `SA_RESTART` - restart system calls interrupted by the signal instead
+    /// of failing them with EINTR.
+    pub fn restart(self, on: bool) -> Self {
+        self.flag(libc::SA_RESTART, on)
+    }
+
+    /// `SA_NODEFER` - don't block the signal while its handler runs.
+    pub fn no_defer(self, on: bool) -> Self {
+        self.flag(libc::SA_NODEFER, on)
+    }
+
+    /// `SA_RESETHAND` - restore the default disposition after the first
+    /// delivery. Call `.resume()` to handle the signal again.
+    pub fn reset_hand(self, on: bool) -> Self {
+        self.flag(libc::SA_RESETHAND, on)
+    }
+
+    /// `SA_ONSTACK` - run the handler on the thread's alternate signal stack.
+    /// Always on once `addy::altstack` is in use.
+    pub fn on_stack(self, on: bool) -> Self {
+        self.flag(libc::SA_ONSTACK, on)
+    }
+
+    /// `SA_NOCLDSTOP` - only meaningful for SIGCHLD. Don't deliver it when a
+    /// child stops or continues.
+    pub fn no_child_stop(self, on: bool) -> Self {
+        self.flag(libc::SA_NOCLDSTOP, on)
+    }
+
+    /// `SA_NOCLDWAIT` - only meaningful for SIGCHLD. Children don't become
+    /// zombies when they exit.
+    pub fn no_child_wait(self, on: bool) -> Self {
+        self.flag(libc::SA_NOCLDWAIT, on)
+    }
+
+    /// Block only these signals while the handler runs (plus the signal
+    /// itself unless `.no_defer(true)`).
+    pub fn mask(mut self, signals: &[Signal]) -> Self {
+        let mask = signals
+            .iter()
+            .fold(0u64, |mask, signal| mask | 1 << (*signal as u32));
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Block every signal while the handler runs. This is the default.
+    pub fn mask_all(mut self) -> Self {
+        self.mask = None;
+        self
+    }
+
+    /// True if the options include `SA_RESETHAND`.
+    pub(crate) fn resets(&self) -> bool {
+        self.flags & libc::SA_RESETHAND != 0
+    }
+
+    /* Builds the sigaction(...) struct pointing at handler */
+    pub(crate) fn sigaction(&self, handler: libc::sighandler_t) -> libc::sigaction {
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = handler;
+            action.sa_flags = libc::SA_SIGINFO | self.flags;
+            if crate::altstack::enabled() {
+                action.sa_flags |= libc::SA_ONSTACK;
+            }
+
+            match self.mask {
+                None => {
+                    libc::sigfillset(&mut action.sa_mask);
+                }
+                Some(mask) => {
+                    libc::sigemptyset(&mut action.sa_mask);
+                    for signal in Signal::iterator() {
+                        if mask & 1 << (signal as u32) != 0 {
+                            libc::sigaddset(&mut action.sa_mask, signal as libc::c_int);
+                        }
+                    }
+                }
+            }
+            action
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Signal handling described in a config file instead of in code.
+//!
+//! A `Policy` maps signal names to a disposition (`default`, `ignore` or
+//! `handled`) and the names of the actions to run when they arrive. Actions
+//! are either built in or registered by the application in an `Actions`.
+//! `.validate(...)` checks a policy without changing anything,
+//! `.apply(...)` makes every change at once so no signal is handled half way
+//! through.
+//!
+//! Only built with the `serde` feature. `Policy::from_toml(...)` needs the
+//! `toml` feature and `Policy::from_json(...)` the `serde_json` feature.
+//!
+//! # Built In Actions
+//! * `log` writes the signal's name to stderr
+//! * `exit` terminates the process the way the signal would have by default
+//!
+//! # Example
+//! ```
+//! use addy::policy::{Actions, Policy, Disposition, SignalPolicy};
+//!
+//! fn main() -> Result<(), addy::policy::PolicyError> {
+//!     let policy = Policy::new()
+//!             .signal("SIGPIPE", SignalPolicy::new(Disposition::Ignore))
+//!             .signal("SIGUSR2", SignalPolicy::handled(&["reopen_logs"]))
+//!             .signal("SIGQUIT", SignalPolicy::handled(&["dump_state", "exit"]));
+//!
+//!     let actions = Actions::new()
+//!             .add("reopen_logs", |_signal| { /* Reopen the log files */ })
+//!             .add("dump_state", |_signal| { /* Write out the state */ });
+//!
+//!     policy.validate(&actions)?;
+//!     policy.apply(&actions)?;
+//!     Ok(())
+//! }
+//! ```
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Action, Error, Mediator, Signal, SignalHandle, SIGKILL, SIGSTOP};
+
+/* Callbacks a policy registers go here, so applying another policy can
+ * clear them first.
+*/
+const NAMESPACE: &str = "addy::policy";
+
+/* The signals the last policy applied to each Mediator handled, by slot. A
+ * policy that leaves one out puts its default behaviour back.
+*/
+static HANDLED: Mutex<BTreeMap<usize, Vec<Signal>>> = Mutex::new(BTreeMap::new());
+
+/* The Mediator in slot went away, the next one there starts fresh */
+pub(crate) fn forget(slot: usize) {
+    HANDLED.lock().unwrap().remove(&slot);
+}
+
+/**********
+ * POLICY *
+ **********/
+
+/// What the process does when a signal arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    /// The signal's default behaviour, like `SignalHandle::default()`.
+    Default,
+    /// Nothing happens, like `SignalHandle::ignore()`.
+    Ignore,
+    /// Addy handles it and runs the signal's actions, like
+    /// `SignalHandle::enable()`.
+    Handled,
+}
+
+/// The disposition and actions for one signal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignalPolicy {
+    /// Defaults to `handled` if there are actions.
+    #[serde(default)]
+    disposition: Option<Disposition>,
+    /// Names of the actions to run, in no particular order.
+    #[serde(default)]
+    actions: Vec<String>,
+}
+
+impl SignalPolicy {
+    /// A signal with a disposition and no actions.
+    pub fn new(disposition: Disposition) -> Self {
+        SignalPolicy {
+            disposition: Some(disposition),
+            actions: Vec::new(),
+        }
+    }
+
+    /// A handled signal that runs `actions`.
+    pub fn handled<A: AsRef<str>>(actions: &[A]) -> Self {
+        SignalPolicy {
+            disposition: Some(Disposition::Handled),
+            actions: actions
+                .iter()
+                .map(|action| String::from(action.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// The disposition, `handled` if it wasn't given and there are actions.
+    pub fn disposition(&self) -> Disposition {
+        match self.disposition {
+            Some(disposition) => disposition,
+            None if self.actions.is_empty() => Disposition::Default,
+            None => Disposition::Handled,
+        }
+    }
+
+    /// The names of the actions to run.
+    pub fn actions(&self) -> &[String] {
+        &self.actions
+    }
+}
+
+/// How a set of signals should be handled, keyed by signal name ("SIGHUP",
+/// "HUP" and "hup" all work).
+///
+/// In TOML:
+/// ```toml
+/// [signals.SIGPIPE]
+/// disposition = "ignore"
+///
+/// [signals.SIGUSR2]
+/// actions = ["reopen_logs"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    signals: BTreeMap<String, SignalPolicy>,
+}
+
+impl Policy {
+    /// A policy that doesn't change anything.
+    pub fn new() -> Self {
+        Policy::default()
+    }
+
+    /// Sets the policy for the signal called `name`.
+    pub fn signal<A: AsRef<str>>(mut self, name: A, policy: SignalPolicy) -> Self {
+        self.signals.insert(String::from(name.as_ref()), policy);
+        self
+    }
+
+    /// The policy for each signal, by the name it was given.
+    pub fn signals(&self) -> &BTreeMap<String, SignalPolicy> {
+        &self.signals
+    }
+
+    /// Reads a policy from TOML.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::policy::{Actions, Disposition, Policy};
+    ///
+    /// fn main() -> Result<(), addy::policy::PolicyError> {
+    ///     let policy = Policy::from_toml(r#"
+    ///         [signals.SIGPIPE]
+    ///         disposition = "ignore"
+    ///
+    ///         [signals.USR2]
+    ///         actions = ["reopen_logs"]
+    ///     "#)?;
+    ///     assert_eq!(policy.signals()["SIGPIPE"].disposition(), Disposition::Ignore);
+    ///     assert_eq!(policy.signals()["USR2"].disposition(), Disposition::Handled);
+    ///
+    ///     let actions = Actions::new().add("reopen_logs", |_signal| { /* Reopen the log files */ });
+    ///     policy.apply(&actions)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, PolicyError> {
+        toml::from_str(toml).map_err(|err| PolicyError::Parse(err.to_string()))
+    }
+
+    /// Reads a policy from JSON.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::policy::{Actions, Disposition, Policy};
+    ///
+    /// fn main() -> Result<(), addy::policy::PolicyError> {
+    ///     let policy = Policy::from_json(r#"{
+    ///         "signals": {
+    ///             "SIGPIPE": { "disposition": "ignore" },
+    ///             "SIGQUIT": { "actions": ["log", "exit"] }
+    ///         }
+    ///     }"#)?;
+    ///     assert_eq!(policy.signals()["SIGPIPE"].disposition(), Disposition::Ignore);
+    ///     assert_eq!(policy.signals()["SIGQUIT"].actions(), ["log", "exit"]);
+    ///
+    ///     policy.apply(&Actions::new())?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(json: &str) -> Result<Self, PolicyError> {
+        serde_json::from_str(json).map_err(|err| PolicyError::Parse(err.to_string()))
+    }
+
+    /// Checks the policy could be applied with `actions` without applying
+    /// it: every signal exists and is only named once, every action is
+    /// known, and only handled signals have actions.
+    pub fn validate(&self, actions: &Actions) -> Result<(), PolicyError> {
+        self.resolve(actions).map(|_| ())
+    }
+
+    /// Validates the policy, then makes every change it describes to the
+    /// default Mediator at once. Callbacks registered by a previously
+    /// applied policy are removed first, and signals it handled that this
+    /// policy doesn't mention go back to their default behaviour. Other
+    /// signals the policy doesn't mention are left alone.
+    ///
+    /// Nothing changes if it returns an error.
+    pub fn apply(&self, actions: &Actions) -> Result<(), PolicyError> {
+        self.apply_to(&Mediator::global(), actions)
+    }
+
+    /// Like `.apply(...)` but for `mediator`.
+    pub fn apply_to(&self, mediator: &Mediator, actions: &Actions) -> Result<(), PolicyError> {
+        let resolved = self.resolve(actions)?;
+        let handled: Vec<Signal> = resolved
+            .iter()
+            .filter(|(_, disposition, _)| *disposition == Disposition::Handled)
+            .map(|(signal, _, _)| *signal)
+            .collect();
+        let mentioned: Vec<Signal> = resolved.iter().map(|(signal, _, _)| *signal).collect();
+
+        /* Record what the SignalHandle calls would send instead of sending
+         * it, so the Event Loop gets everything in one Action.
+        	*/
+        let (recorder, recorded) = mpsc::channel();
+        for (signal, disposition, callbacks) in resolved {
+            let mut handle = SignalHandle {
+                signal,
+                sender: recorder.clone(),
+                namespace: String::from(NAMESPACE),
+                tags: Vec::new(),
+                caller_driven: false,
+            };
+            match disposition {
+                Disposition::Default => handle.default()?,
+                Disposition::Ignore => handle.ignore()?,
+                Disposition::Handled => {
+                    for (name, callback) in callbacks {
+                        handle.register(name, move |signal| callback(signal))?;
+                    }
+                    handle.enable()?
+                }
+            };
+        }
+        drop(recorder);
+
+        /* Held until the batch is sent so two policies can't interleave */
+        let mut previous = HANDLED.lock().unwrap();
+        let slot = mediator.slot();
+        let mut batch = vec![Action::ClearNamespace(String::from(NAMESPACE))];
+        if let Some(signals) = previous.get(&slot) {
+            batch.extend(
+                signals
+                    .iter()
+                    .filter(|signal| !mentioned.contains(signal))
+                    .map(|signal| Action::Default(*signal)),
+            );
+        }
+        batch.extend(recorded.try_iter());
+        mediator.send(Action::Batch(batch))?;
+        previous.insert(slot, handled);
+        Ok(())
+    }
+
+    /* Looks up every signal and action, or returns the first problem */
+    fn resolve(&self, actions: &Actions) -> Result<Vec<Resolved>, PolicyError> {
+        let mut resolved: Vec<Resolved> = Vec::new();
+        for (name, policy) in &self.signals {
+            let signal: Signal = name
+                .parse()
+                .map_err(|_| PolicyError::UnknownSignal(name.clone()))?;
+            /* "SIGHUP", "HUP" and "hup" are different keys */
+            if resolved.iter().any(|(seen, _, _)| *seen == signal) {
+                return Err(PolicyError::Duplicate(signal));
+            }
+
+            let disposition = policy.disposition();
+            if (signal == SIGKILL || signal == SIGSTOP) && disposition != Disposition::Default {
+                return Err(PolicyError::Uncatchable(signal));
+            }
+            if disposition != Disposition::Handled && !policy.actions.is_empty() {
+                return Err(PolicyError::NotHandled(signal));
+            }
+
+            let mut callbacks = Vec::new();
+            for action in &policy.actions {
+                let callback = actions
+                    .get(action)
+                    .ok_or_else(|| PolicyError::UnknownAction {
+                        signal,
+                        action: action.clone(),
+                    })?;
+                callbacks.push((action.clone(), callback));
+            }
+            resolved.push((signal, disposition, callbacks));
+        }
+        Ok(resolved)
+    }
+}
+
+type Callback = Arc<dyn Fn(Signal) + Send + Sync>;
+type Resolved = (Signal, Disposition, Vec<(String, Callback)>);
+
+/***********
+ * ACTIONS *
+ ***********/
+
+/// The actions a `Policy` can name. Starts out with the built in ones.
+#[derive(Clone)]
+pub struct Actions {
+    actions: BTreeMap<String, Callback>,
+}
+
+impl std::fmt::Debug for Actions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.actions.keys()).finish()
+    }
+}
+
+impl Default for Actions {
+    fn default() -> Self {
+        Actions::new()
+    }
+}
+
+impl Actions {
+    /// Just the built in actions, `log` and `exit`.
+    pub fn new() -> Self {
+        Actions {
+            actions: BTreeMap::new(),
+        }
+        .add("log", |signal| eprintln!("Received {}", signal))
+        .add("exit", |signal| crate::reset_and_raise(signal))
+    }
+
+    /// Adds an action policies can name, replacing any with the same name
+    /// (built in ones too).
+    pub fn add<A, F>(mut self, name: A, action: F) -> Self
+    where
+        A: AsRef<str>,
+        F: Fn(Signal) + Send + Sync + 'static,
+    {
+        self.actions
+            .insert(String::from(name.as_ref()), Arc::new(action));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<Callback> {
+        self.actions.get(name).cloned()
+    }
+}
+
+/**********
+ * ERRORS *
+ **********/
+
+/// Why a `Policy` couldn't be loaded, validated or applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The TOML or JSON didn't describe a policy.
+    Parse(String),
+    /// No signal has this name on this platform.
+    UnknownSignal(String),
+    /// The signal is in the policy under more than one name, e.g. "SIGHUP"
+    /// and "HUP".
+    Duplicate(Signal),
+    /// The signal names an action that isn't built in or in the `Actions`.
+    UnknownAction {
+        /// The signal naming it.
+        signal: Signal,
+        /// The action's name.
+        action: String,
+    },
+    /// The signal has actions but isn't handled, so they would never run.
+    NotHandled(Signal),
+    /// SIGKILL and SIGSTOP can only have their default behaviour.
+    Uncatchable(Signal),
+    /// Sending the changes to the Event Loop failed.
+    Addy(Error),
+}
+
+impl From<Error> for PolicyError {
+    fn from(err: Error) -> Self {
+        PolicyError::Addy(err)
+    }
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::Parse(err) => write!(f, "Invalid signal policy: {}", err),
+            PolicyError::UnknownSignal(name) => write!(f, "Unknown signal: {}", name),
+            PolicyError::Duplicate(signal) => {
+                write!(f, "{} is in the policy more than once.", signal)
+            }
+            PolicyError::UnknownAction { signal, action } => {
+                write!(f, "Unknown action for {}: {}", signal, action)
+            }
+            PolicyError::NotHandled(signal) => {
+                write!(f, "{} has actions but isn't handled.", signal)
+            }
+            PolicyError::Uncatchable(signal) => {
+                write!(f, "{} can only have its default behaviour.", signal)
+            }
+            PolicyError::Addy(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PolicyError::Addy(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! SIGHUP driven reloading of a value, usually your configuration.
+//!
+//! On every SIGHUP the loader you provide is called. If it succeeds the new
+//! value is swapped in atomically, if it fails the old value is kept and the
+//! error is recorded. Readers hold a cheap `Handle` that always sees the latest
+//! good value.
+//!
+//! # Example
+//! ```
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! struct Config {
+//!     verbose: bool,
+//! }
+//!
+//! fn load() -> Result<Config, std::io::Error> {
+//!     /* Read and parse your config file */
+//!     Ok(Config { verbose: true })
+//! }
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let reloader = addy::reload::install(load()?, load)?;
+//!     let config = reloader.handle();
+//!
+//!     std::thread::spawn(move || loop {
+//!         if config.get().verbose {
+//!             /* Be chatty */
+//!         }
+//!         std::thread::sleep(Duration::from_millis(10));
+//!     });
+//!
+//!     /* Normally `kill -HUP <pid>` starts a reload */
+//!     reloader.reload();
+//!     assert_eq!(reloader.status().reloads, 1);
+//!     Ok(())
+//! }
+//! ```
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use crate::{Error, SIGHUP};
+
+/* Every Reloader registers its own SIGHUP callback, so they each need a
+ * unique name or they'd replace each other.
+*/
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+type Loader<T> = Box<dyn Fn() -> Result<T, String> + Send>;
+type ErrorHook = Box<dyn Fn(&str) + Send>;
+
+struct Shared<T> {
+    /* The Arc swap - readers clone the inner Arc under the read lock */
+    current: RwLock<Arc<T>>,
+    loader: Mutex<Loader<T>>,
+    on_error: Mutex<Option<ErrorHook>>,
+    status: Mutex<Status>,
+}
+
+impl<T> Shared<T> {
+    fn reload(&self) -> bool {
+        let failed = {
+            /* Holding the loader lock serialises reloads */
+            let loader = self.loader.lock().unwrap();
+            let result = loader();
+
+            let mut status = self.status.lock().unwrap();
+            status.last_attempt = Some(SystemTime::now());
+            match result {
+                Ok(value) => {
+                    *self.current.write().unwrap() = Arc::new(value);
+                    status.reloads += 1;
+                    status.last_error = None;
+                    None
+                }
+                Err(err) => {
+                    status.failures += 1;
+                    status.last_error = Some(err.clone());
+                    Some(err)
+                }
+            }
+        };
+
+        /* Not under the locks, so the hook can look at .status() */
+        match failed {
+            Some(err) => {
+                if let Some(hook) = self.on_error.lock().unwrap().as_ref() {
+                    hook(&err);
+                }
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+/// Counters and the outcome of the last reload.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Number of successful reloads.
+    pub reloads: u64,
+    /// Number of reloads where the loader returned an error.
+    pub failures: u64,
+    /// The error from the last attempt, None if it succeeded.
+    pub last_error: Option<String>,
+    /// When the loader was last called.
+    pub last_attempt: Option<SystemTime>,
+}
+
+/// Owns the reloadable value. Created with `addy::reload::install(...)`.
+pub struct Reloader<T> {
+    id: usize,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> std::fmt::Debug for Reloader<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reloader")
+            .field("id", &self.id)
+            .field("status", &*self.shared.status.lock().unwrap())
+            .finish()
+    }
+}
+
+impl<T> Reloader<T> {
+    /// Returns a cheap handle readers can use to get the latest value.
+    pub fn handle(&self) -> Handle<T> {
+        Handle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Returns the latest good value.
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.shared.current.read().unwrap())
+    }
+
+    /// Runs the loader now, as if a SIGHUP had arrived. Returns true if the
+    /// new value was swapped in.
+    pub fn reload(&self) -> bool {
+        self.shared.reload()
+    }
+
+    /// Returns the reload counters and last error.
+    pub fn status(&self) -> Status {
+        self.shared.status.lock().unwrap().clone()
+    }
+
+    /// Calls `hook` with the error whenever a reload fails. Replaces any
+    /// previous hook.
+    pub fn on_error<F>(&self, hook: F) -> &Self
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        self.shared.on_error.lock().unwrap().replace(Box::new(hook));
+        self
+    }
+
+    /// Stops reloading on SIGHUP. Handles keep seeing the last good value.
+    /// SIGHUP goes back to its default behaviour unless other callbacks are
+    /// registered for it.
+    pub fn uninstall(self) -> Result<(), Error> {
+        crate::mediate(SIGHUP).retire(callback_name(self.id))?;
+        Ok(())
+    }
+}
+
+/// A cheap, cloneable reader of the latest good value.
+pub struct Handle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Handle").field(&self.get()).finish()
+    }
+}
+
+impl<T> Handle<T> {
+    /// Returns the latest good value. The value stays alive as long as you
+    /// hold the Arc, even if a reload swaps in a new one.
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.shared.current.read().unwrap())
+    }
+}
+
+fn callback_name(id: usize) -> String {
+    format!("addy::reload::{}", id)
+}
+
+/// Starts with `initial` and calls `loader` on every SIGHUP.
+///
+/// The loader runs on the Event Loop thread, keep it quick.
+pub fn install<T, E, F>(initial: T, loader: F) -> Result<Reloader<T>, Error>
+where
+    T: Send + Sync + 'static,
+    E: std::fmt::Display,
+    F: Fn() -> Result<T, E> + Send + 'static,
+{
+    let loader: Loader<T> = Box::new(move || loader().map_err(|err| err.to_string()));
+    let shared = Arc::new(Shared {
+        current: RwLock::new(Arc::new(initial)),
+        loader: Mutex::new(loader),
+        on_error: Mutex::new(None),
+        status: Mutex::new(Status::default()),
+    });
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let callback = Arc::clone(&shared);
+    crate::mediate(SIGHUP)
+        .register(callback_name(id), move |_signal| {
+            callback.reload();
+        })?
+        .enable()?;
+
+    Ok(Reloader { id, shared })
+}
@@ -0,0 +1,216 @@
+/* Where things that go wrong away from the caller end up.
+ *
+ * Callbacks run on the Event Loop (or an Execution's threads) and handlers
+ * are installed from there too, so nobody is around to return an Error to.
+ * Failures are handed to the hook set with addy::on_error(...) instead, or
+ * written to stderr if there isn't one.
+*/
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use crate::{Error, Signal, SignalEvent};
+
+type Hook = Arc<dyn Fn(AddyError) + Send + Sync>;
+
+static HOOK: Mutex<Option<Hook>> = Mutex::new(None);
+
+/// What went wrong. Part of an `AddyError`.
+#[derive(Debug)]
+pub enum Cause {
+    /// A callback registered with `.register_fallible()` returned an error.
+    Callback(Box<dyn std::error::Error + Send>),
+    /// A callback panicked, with the panic's message if it had one.
+    Panic(String),
+    /// The kernel refused to change how the signal is handled.
+    Sigaction(Error),
+    /// `CallbackContext::trigger(...)` was nested deeper than the limit, so
+    /// the signal's callbacks weren't run.
+    TriggerDepth(usize),
+}
+
+/// Something that went wrong while handling a signal, handed to the hook set
+/// with `addy::on_error(...)`.
+#[derive(Debug)]
+pub struct AddyError {
+    signal: Signal,
+    callback: Option<String>,
+    cause: Cause,
+}
+
+impl AddyError {
+    /// The signal it happened for.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// The name of the callback that failed, None if it wasn't a callback.
+    pub fn callback(&self) -> Option<&str> {
+        self.callback.as_deref()
+    }
+
+    /// What went wrong.
+    pub fn cause(&self) -> &Cause {
+        &self.cause
+    }
+}
+
+impl std::fmt::Display for Cause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cause::Callback(err) => write!(f, "{}", err),
+            Cause::Panic(message) => write!(f, "panicked: {}", message),
+            Cause::Sigaction(err) => write!(f, "sigaction failed: {}", err),
+            Cause::TriggerDepth(depth) => {
+                write!(f, "triggered more than {} deep, not run", depth)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AddyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.callback {
+            Some(name) => write!(f, "{} callback \"{}\": {}", self.signal, name, self.cause),
+            None => write!(f, "{}: {}", self.signal, self.cause),
+        }
+    }
+}
+
+impl std::error::Error for AddyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.cause {
+            Cause::Callback(err) => Some(err.as_ref()),
+            Cause::Sigaction(err) => Some(err),
+            Cause::Panic(_) | Cause::TriggerDepth(_) => None,
+        }
+    }
+}
+
+/// Sets the hook every `AddyError` is handed to, replacing the previous one.
+/// Without one they're written to stderr.
+///
+/// The hook runs wherever the error happened, usually the Event Loop, so it
+/// should be quick.
+///
+/// A callback that panics on the Event Loop is reported and the Event Loop
+/// carries on with the other callbacks and later signals.
+///
+/// # Example
+/// ```
+/// use addy::SIGHUP;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     addy::on_error(|err| {
+///         eprintln!("{:?} failed on {}: {}", err.callback(), err.signal(), err.cause());
+///     });
+///
+///     addy::mediate(SIGHUP)
+///             .register_fallible("reload", |_signal| std::fs::read("config.toml").map(|_| ()))?
+///             .enable()?;
+///     Ok(())
+/// }
+/// ```
+///
+/// # Panicking Callbacks
+/// ```
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+/// use addy::SIGUSR2;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let (tx, rx) = mpsc::channel();
+///     addy::on_error(|err| eprintln!("{}", err));
+///
+///     addy::mediate(SIGUSR2)
+///             .register("broken", |_signal| panic!("oops"))?
+///             .register("working", move |_signal| { let _ = tx.send(()); })?
+///             .enable()?;
+///     std::thread::sleep(Duration::from_millis(100));
+///
+///     /* Still handled after "broken" has panicked */
+///     SIGUSR2.raise()?;
+///     assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+///     SIGUSR2.raise()?;
+///     assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+///     Ok(())
+/// }
+/// ```
+pub fn on_error<F>(hook: F)
+where
+    F: Fn(AddyError) + Send + Sync + 'static,
+{
+    *HOOK.lock().unwrap() = Some(Arc::new(hook));
+}
+
+/* Hands err to the hook. Not held across the call so the hook can replace
+ * itself.
+*/
+fn report(err: AddyError) {
+    let hook = HOOK.lock().unwrap_or_else(|err| err.into_inner()).clone();
+    match hook {
+        Some(hook) => hook(err),
+        None => eprintln!("addy: {}", err),
+    }
+}
+
+/* The kernel refused a sigaction(...) for signal */
+pub(crate) fn sigaction(signal: Signal, err: Error) {
+    report(AddyError {
+        signal,
+        callback: None,
+        cause: Cause::Sigaction(err),
+    });
+}
+
+/* A triggered signal was dropped for being nested too deep */
+pub(crate) fn trigger_depth(signal: Signal, depth: usize) {
+    report(AddyError {
+        signal,
+        callback: None,
+        cause: Cause::TriggerDepth(depth),
+    });
+}
+
+/* A fallible callback returned err */
+pub(crate) fn failed<E>(signal: Signal, name: &str, err: E)
+where
+    E: std::error::Error + Send + 'static,
+{
+    report(AddyError {
+        signal,
+        callback: Some(String::from(name)),
+        cause: Cause::Callback(Box::new(err)),
+    });
+}
+
+/* Panics carry a &str or a String, anything else has no message */
+fn message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+/* Wraps a callback so a panic is reported before it carries on, wherever
+ * the callback is running decides what happens to it.
+*/
+pub(crate) fn guard<F>(signal: Signal, name: &str, callback: F) -> impl Fn(&SignalEvent) + Send
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    let name = String::from(name);
+    move |event: &SignalEvent| {
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| callback(event))) {
+            report(AddyError {
+                signal,
+                callback: Some(name.clone()),
+                cause: Cause::Panic(message(panic.as_ref())),
+            });
+            panic::resume_unwind(panic);
+        }
+    }
+}
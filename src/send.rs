@@ -0,0 +1,171 @@
+/* Safe wrappers around the libc functions that *send* signals. The rest of
+ * Addy is about receiving them - this is the other half so callers don't need
+ * their own unsafe {} blocks next to it.
+ *
+ * Every wrapper returns the errno the kernel hands back as an addy::Error.
+*/
+use std::os::unix::thread::RawPthread;
+
+use crate::{Error, Signal};
+
+/***********
+ * HELPERS *
+ ***********/
+
+/* Most of libc's signal senders return -1 and set errno */
+fn check(ret: libc::c_int) -> Result<(), Error> {
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/* pid 0 and negative pids mean "my process group" and "every process I can
+ * signal" to kill(2). We want callers to reach those on purpose with
+ * send_group(...) instead of by accident with a bad pid.
+*/
+fn check_pid(pid: libc::pid_t) -> Result<(), Error> {
+    if pid <= 0 {
+        Err(Error::InvalidArgument)
+    } else {
+        Ok(())
+    }
+}
+
+/*********
+ * RAISE *
+ *********/
+
+impl Signal {
+    /// Sends this signal to the calling thread. If a handler is installed it
+    /// runs before raise() returns.
+    ///
+    /// # Example
+    /// ```
+    /// use addy::SIGWINCH;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     /* SIGWINCH is ignored by default so this is harmless */
+    ///     SIGWINCH.raise()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn raise(self) -> Result<(), Error> {
+        match unsafe { libc::raise(self as libc::c_int) } {
+            0 => Ok(()),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+}
+
+/********
+ * SEND *
+ ********/
+
+/// Sends a signal to the process with the given pid. The pid must be
+/// positive, use addy::send_group(...) to signal a process group.
+///
+/// # Errors
+/// * `Error::NoSuchProcess` if the process doesn't exist (ESRCH)
+/// * `Error::PermissionDenied` if you aren't allowed to signal it (EPERM)
+/// * `Error::InvalidArgument` if the pid is zero or negative
+///
+/// # Example
+/// ```
+/// use addy::SIGWINCH;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let me = std::process::id() as libc::pid_t;
+///     addy::send(me, SIGWINCH)?;
+///     Ok(())
+/// }
+/// ```
+pub fn send(pid: libc::pid_t, signal: Signal) -> Result<(), Error> {
+    check_pid(pid)?;
+    check(unsafe { libc::kill(pid, signal as libc::c_int) })
+}
+
+/// Sends a signal to every process in the process group pgid. The pgid must
+/// be positive.
+///
+/// # Errors
+/// * `Error::NoSuchProcess` if the process group doesn't exist (ESRCH)
+/// * `Error::PermissionDenied` if you can't signal any process in it (EPERM)
+/// * `Error::InvalidArgument` if the pgid is zero or negative
+///
+/// # Example
+/// ```no_run
+/// use addy::SIGTERM;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     /* Tell the workers we spawned into their own group to shut down */
+///     let workers: libc::pid_t = 4242;
+///     addy::send_group(workers, SIGTERM)?;
+///     Ok(())
+/// }
+/// ```
+pub fn send_group(pgid: libc::pid_t, signal: Signal) -> Result<(), Error> {
+    check_pid(pgid)?;
+    check(unsafe { libc::killpg(pgid, signal as libc::c_int) })
+}
+
+/// Queues a signal along with a value to the process with the given pid
+/// using sigqueue(3). The receiver sees the value in `si_value` and unlike
+/// addy::send(...) queued real-time signals aren't merged.
+///
+/// # Errors
+/// * `Error::NoSuchProcess` if the process doesn't exist (ESRCH)
+/// * `Error::PermissionDenied` if you aren't allowed to signal it (EPERM)
+/// * `Error::QueueFull` if the queued signal limit was reached (EAGAIN)
+/// * `Error::InvalidArgument` if the pid is zero or negative
+///
+/// # Example
+/// ```
+/// use addy::SIGWINCH;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let me = std::process::id() as libc::pid_t;
+///     addy::queue(me, SIGWINCH, 42)?;
+///     Ok(())
+/// }
+/// ```
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn queue(pid: libc::pid_t, signal: Signal, value: usize) -> Result<(), Error> {
+    check_pid(pid)?;
+    let value = libc::sigval {
+        sival_ptr: value as *mut libc::c_void,
+    };
+    check(unsafe { libc::sigqueue(pid, signal as libc::c_int, value) })
+}
+
+/// Sends a signal to a specific thread in this process with pthread_kill(3).
+/// Get the thread's id from a JoinHandle with
+/// `std::os::unix::thread::JoinHandleExt::as_pthread_t()`.
+///
+/// # Errors
+/// * `Error::NoSuchProcess` if the thread has already exited (ESRCH)
+///
+/// # Example
+/// ```
+/// use addy::SIGWINCH;
+/// use std::os::unix::thread::JoinHandleExt;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let (tx, rx) = std::sync::mpsc::channel::<()>();
+///     let worker = std::thread::spawn(move || { let _ = rx.recv(); });
+///
+///     addy::send_thread(worker.as_pthread_t(), SIGWINCH)?;
+///
+///     drop(tx);
+///     worker.join().unwrap();
+///     Ok(())
+/// }
+/// ```
+pub fn send_thread(thread: RawPthread, signal: Signal) -> Result<(), Error> {
+    /* pthread_kill returns the error number instead of setting errno */
+    match unsafe { libc::pthread_kill(thread, signal as libc::c_int) } {
+        0 => Ok(()),
+        errno => Err(Error::from_errno(errno)),
+    }
+}
@@ -0,0 +1,453 @@
+/* Graceful shutdown on top of mediate(SIGINT) & mediate(SIGTERM).
+ *
+ * The first signal flips every ShutdownToken and starts a coordinator thread
+ * that runs the registered hooks phase by phase. A second signal inside the
+ * escalation window, or hooks that blow through the deadline, put the default
+ * disposition back and re-raise the signal so the process dies the way it
+ * would have without Addy.
+*/
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Signal, SIGINT, SIGTERM};
+
+/* Every Shutdown registers its own callbacks, so they each need a unique
+ * name or a second one would replace the first one's.
+*/
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/* Tells ShutdownTokens apart so each keeps at most one waker */
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/* Hooks only ever run once */
+type Hook = Box<dyn FnOnce() + Send>;
+
+/* A named group of hooks that must finish before the next phase starts */
+struct Phase {
+    name: String,
+    timeout: Duration,
+    hooks: Vec<(String, Hook)>,
+}
+
+/* Everything the tokens and the coordinator need to see */
+#[derive(Default)]
+struct State {
+    /* When and by what signal (None for .trigger()) shutdown was started */
+    triggered: Option<(Instant, Option<Signal>)>,
+    /* Set once every phase has run */
+    complete: bool,
+    /* The task awaiting each ShutdownToken, by token */
+    wakers: BTreeMap<u64, Waker>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    changed: Condvar,
+    phases: Mutex<Vec<Phase>>,
+    escalation_window: Duration,
+    deadline: Duration,
+}
+
+/* Hooks can't be printed, so just print the shape */
+impl std::fmt::Debug for Inner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases: Vec<String> = self
+            .phases
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|phase| phase.name.clone())
+            .collect();
+        f.debug_struct("Shutdown")
+            .field("phases", &phases)
+            .field("escalation_window", &self.escalation_window)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl Inner {
+    fn trigger(self: &Arc<Self>, signal: Option<Signal>) {
+        let mut state = self.state.lock().unwrap();
+
+        /* Already shutting down - a second signal soon enough escalates */
+        if let Some((when, _)) = state.triggered {
+            if let Some(signal) = signal {
+                if when.elapsed() <= self.escalation_window {
+                    drop(state);
+                    crate::reset_and_raise(signal);
+                }
+            }
+            return;
+        }
+
+        state.triggered = Some((Instant::now(), signal));
+        for (_, waker) in std::mem::take(&mut state.wakers) {
+            waker.wake();
+        }
+        self.changed.notify_all();
+        drop(state);
+
+        /* Don't hold up the Event Loop while the hooks run */
+        let inner = Arc::clone(self);
+        thread::spawn(move || inner.run_phases(signal));
+    }
+
+    fn run_phases(&self, signal: Option<Signal>) {
+        let start = Instant::now();
+
+        /* Take the hooks out so late .hook() calls can't race the phases */
+        let phases: Vec<(Duration, Vec<(String, Hook)>)> = self
+            .phases
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .map(|phase| (phase.timeout, std::mem::take(&mut phase.hooks)))
+            .collect();
+
+        for (timeout, hooks) in phases {
+            let remaining = self.deadline.checked_sub(start.elapsed());
+            let remaining = match remaining {
+                Some(remaining) => remaining,
+                None => Self::overrun(signal),
+            };
+
+            /* Each phase gets its own thread so we can give up on it */
+            let (done, finished) = mpsc::channel();
+            thread::spawn(move || {
+                for (_name, hook) in hooks {
+                    /* One bad hook shouldn't stop the rest of the phase */
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(hook));
+                }
+                let _ = done.send(());
+            });
+
+            if finished.recv_timeout(timeout.min(remaining)).is_err()
+                && start.elapsed() >= self.deadline
+            {
+                Self::overrun(signal);
+            }
+        }
+
+        self.state.lock().unwrap().complete = true;
+        self.changed.notify_all();
+    }
+
+    /* Out of time - die the way we would have if nobody handled the signal */
+    fn overrun(signal: Option<Signal>) -> ! {
+        match signal {
+            Some(signal) => crate::reset_and_raise(signal),
+            None => std::process::exit(1),
+        }
+    }
+}
+
+/************
+ * SHUTDOWN *
+ ************/
+
+/// Coordinates a graceful shutdown when the process receives SIGINT or
+/// SIGTERM (or whichever signals you pick).
+///
+/// * The first signal flips every `ShutdownToken` handed out.
+/// * Hooks then run in the order their phases were declared. Each phase has a
+///   timeout, after which the next phase starts anyway.
+/// * A second signal within the escalation window exits immediately.
+/// * If the hooks overrun the deadline the default disposition is restored
+///   and the signal is re-raised, so exit statuses stay correct.
+///
+/// Shutdown is cheap to clone, every clone refers to the same coordinator.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let shutdown = addy::Shutdown::builder()
+///             .phase("drain", Duration::from_secs(5))
+///             .phase("flush", Duration::from_secs(2))
+///             .deadline(Duration::from_secs(10))
+///             .install()?;
+///
+///     shutdown.hook("flush", "logs", || { println!("Flushing logs"); })?
+///             .hook("drain", "http", || { println!("Draining connections"); })?;
+///
+///     let token = shutdown.token();
+///     std::thread::spawn(move || {
+///         while !token.is_shutdown() {
+///             /* Do work */
+///             std::thread::sleep(Duration::from_millis(10));
+///         }
+///     });
+///
+///     /* Normally Ctrl + C or SIGTERM starts this */
+///     shutdown.trigger();
+///     shutdown.wait();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+impl Shutdown {
+    /// Returns a builder to configure the phases, signals and timings.
+    pub fn builder() -> ShutdownBuilder {
+        ShutdownBuilder::default()
+    }
+
+    /// Adds a named hook to a phase. Hooks in the same phase run in the order
+    /// they were added.
+    ///
+    /// # Errors
+    /// `Error::InvalidArgument` if no phase with that name was declared.
+    pub fn hook<P, A, F>(&self, phase: P, name: A, hook: F) -> Result<&Shutdown, Error>
+    where
+        P: AsRef<str>,
+        A: AsRef<str>,
+        F: FnOnce() + Send + 'static,
+    {
+        let mut phases = self.inner.phases.lock().unwrap();
+        let phase = phases
+            .iter_mut()
+            .find(|p| p.name == phase.as_ref())
+            .ok_or(Error::InvalidArgument)?;
+        phase
+            .hooks
+            .push((String::from(name.as_ref()), Box::new(hook)));
+        Ok(self)
+    }
+
+    /// Returns a token that can be polled, waited on or awaited.
+    pub fn token(&self) -> ShutdownToken {
+        ShutdownToken::new(Arc::clone(&self.inner))
+    }
+
+    /// Starts shutting down as if a signal had arrived. If the hooks overrun
+    /// the deadline the process exits with status 1.
+    pub fn trigger(&self) {
+        self.inner.trigger(None);
+    }
+
+    /// Returns true once shutdown has started.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.state.lock().unwrap().triggered.is_some()
+    }
+
+    /// Blocks until shutdown has started and every phase has run.
+    pub fn wait(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        while !state.complete {
+            state = self.inner.changed.wait(state).unwrap();
+        }
+    }
+}
+
+/********************
+ * SHUTDOWN BUILDER *
+ ********************/
+
+/// Configures a `Shutdown`. Created with `addy::Shutdown::builder()`.
+///
+/// Defaults to SIGINT and SIGTERM, a 3 second escalation window and a 30
+/// second deadline.
+#[derive(Debug)]
+pub struct ShutdownBuilder {
+    signals: Vec<Signal>,
+    phases: Vec<(String, Duration)>,
+    escalation_window: Duration,
+    deadline: Duration,
+}
+
+impl Default for ShutdownBuilder {
+    fn default() -> Self {
+        ShutdownBuilder {
+            signals: vec![SIGINT, SIGTERM],
+            phases: Vec::new(),
+            escalation_window: Duration::from_secs(3),
+            deadline: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ShutdownBuilder {
+    /// Declares the next phase. Phases run in the order they are declared, a
+    /// phase that takes longer than its timeout is abandoned.
+    pub fn phase<A: AsRef<str>>(mut self, name: A, timeout: Duration) -> Self {
+        self.phases.push((String::from(name.as_ref()), timeout));
+        self
+    }
+
+    /// Replaces the signals that start a shutdown.
+    pub fn signals(mut self, signals: &[Signal]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// A second signal within this long of the first exits immediately.
+    pub fn escalation_window(mut self, window: Duration) -> Self {
+        self.escalation_window = window;
+        self
+    }
+
+    /// Total time the hooks have before the signal is re-raised.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Registers the shutdown callbacks and enables the signals. Every
+    /// installed Shutdown is started by the signals, so one installed by a
+    /// library doesn't take them away from yours.
+    pub fn install(self) -> Result<Shutdown, Error> {
+        let phases = self
+            .phases
+            .into_iter()
+            .map(|(name, timeout)| Phase {
+                name,
+                timeout,
+                hooks: Vec::new(),
+            })
+            .collect();
+
+        let inner = Arc::new(Inner {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+            phases: Mutex::new(phases),
+            escalation_window: self.escalation_window,
+            deadline: self.deadline,
+        });
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        for signal in self.signals {
+            let inner = Arc::clone(&inner);
+            crate::mediate(signal)
+                .register(callback_name(id), move |signal| inner.trigger(Some(signal)))?
+                .enable()?;
+        }
+
+        Ok(Shutdown { inner })
+    }
+}
+
+fn callback_name(id: u64) -> String {
+    format!("addy::shutdown::{}", id)
+}
+
+/******************
+ * SHUTDOWN TOKEN *
+ ******************/
+
+/// A cheap, cloneable view of whether shutdown has started. It can be polled
+/// with `.is_shutdown()`, blocked on with `.wait()` or awaited.
+///
+/// # Example
+/// ```
+/// fn main() -> Result<(), addy::Error> {
+///     let shutdown = addy::Shutdown::builder().install()?;
+///     let token = shutdown.token();
+///
+///     let worker = std::thread::spawn(move || {
+///         token.wait();
+///         println!("Stopping because of {:?}", token.signal());
+///     });
+///
+///     shutdown.trigger();
+///     worker.join().unwrap();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ShutdownToken {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+/* A clone can be awaited by another task, so it gets its own waker slot */
+impl Clone for ShutdownToken {
+    fn clone(&self) -> Self {
+        ShutdownToken::new(Arc::clone(&self.inner))
+    }
+}
+
+/* A token dropped before shutdown doesn't need waking */
+impl Drop for ShutdownToken {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.inner.state.lock() {
+            state.wakers.remove(&self.id);
+        }
+    }
+}
+
+impl ShutdownToken {
+    fn new(inner: Arc<Inner>) -> Self {
+        ShutdownToken {
+            inner,
+            id: NEXT_TOKEN.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Returns true once shutdown has started.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.state.lock().unwrap().triggered.is_some()
+    }
+
+    /// The signal that started the shutdown. None if it hasn't started or if
+    /// it was started with `Shutdown::trigger()`.
+    pub fn signal(&self) -> Option<Signal> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .triggered
+            .and_then(|(_, signal)| signal)
+    }
+
+    /// Blocks until shutdown has started.
+    pub fn wait(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        while state.triggered.is_none() {
+            state = self.inner.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Blocks until shutdown has started or the timeout elapses. Returns true
+    /// if shutdown has started.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.inner.state.lock().unwrap();
+        let (state, _) = self
+            .inner
+            .changed
+            .wait_timeout_while(state, timeout, |state| state.triggered.is_none())
+            .unwrap();
+        state.triggered.is_some()
+    }
+}
+
+/* Resolves once shutdown has started */
+impl Future for ShutdownToken {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.triggered.is_some() {
+            Poll::Ready(())
+        } else {
+            /* Only the task that polled last gets woken */
+            match state.wakers.get(&self.id) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => {
+                    state.wakers.insert(self.id, cx.waker().clone());
+                }
+            }
+            Poll::Pending
+        }
+    }
+}
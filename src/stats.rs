@@ -0,0 +1,450 @@
+/* Counters for alerting on signals.
+ *
+ * fn c_handler() counts every signal it sees and the Event Loops count what
+ * is still waiting for them, both with plain atomics since handlers can't
+ * take a lock. Callbacks are wrapped when they're registered so they time
+ * themselves, wherever they end up running.
+*/
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{index, Signal, SignalEvent, NUM_SIGNALS};
+
+/* Signals fn c_handler() has seen */
+static RECEIVED: [AtomicU64; NUM_SIGNALS as usize] =
+    [const { AtomicU64::new(0) }; NUM_SIGNALS as usize];
+
+/* Signals that arrived while an earlier one was still pending */
+static COALESCED: [AtomicU64; NUM_SIGNALS as usize] =
+    [const { AtomicU64::new(0) }; NUM_SIGNALS as usize];
+
+/* Deliveries fn c_handler() couldn't send to an Event Loop */
+static DROPPED: [AtomicU64; NUM_SIGNALS as usize] =
+    [const { AtomicU64::new(0) }; NUM_SIGNALS as usize];
+
+/* Deliveries sent to an Event Loop it hasn't got to yet. The Event Loop can
+ * take one off before the handler gets to add it, hence signed.
+*/
+static PENDING: [AtomicI64; NUM_SIGNALS as usize] =
+    [const { AtomicI64::new(0) }; NUM_SIGNALS as usize];
+
+/* Time from fn c_handler() to a callback starting */
+static LATENCY: Mutex<[Timing; NUM_SIGNALS as usize]> =
+    Mutex::new([Timing::new(); NUM_SIGNALS as usize]);
+
+/* Keyed by signal number then callback name so they come out sorted */
+type Callbacks = BTreeMap<(libc::c_int, String), Arc<Mutex<Runs>>>;
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Default)]
+struct Runs {
+    time: Timing,
+    panics: u64,
+}
+
+/**********
+ * TIMING *
+ **********/
+
+/// How many times something happened and how long it took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Timing {
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Timing {
+    const fn new() -> Self {
+        Timing {
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    fn record(&mut self, took: Duration) {
+        self.count += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+    }
+
+    /// How many times it happened.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// How long it took, all together.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The longest it took.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// How long it took on average, zero if it never happened.
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.total.as_secs_f64() / self.count as f64)
+        }
+    }
+}
+
+/*************
+ * RECORDING *
+ *************/
+
+/* Only called from inside fn c_handler(). Returns the running totals of
+ * coalesced and dropped deliveries to hand the callbacks.
+*/
+pub(crate) fn received(signal: Signal) -> (u64, u64) {
+    let signal = index(signal);
+    RECEIVED[signal].fetch_add(1, Ordering::Relaxed);
+    let coalesced = if PENDING[signal].load(Ordering::Relaxed) > 0 {
+        COALESCED[signal].fetch_add(1, Ordering::Relaxed) + 1
+    } else {
+        COALESCED[signal].load(Ordering::Relaxed)
+    };
+    (coalesced, DROPPED[signal].load(Ordering::Relaxed))
+}
+
+/* Only called from inside fn c_handler(), when sending to an Event Loop
+ * failed.
+*/
+pub(crate) fn dropped(signal: Signal) {
+    DROPPED[index(signal)].fetch_add(1, Ordering::Relaxed);
+}
+
+/* Only called from inside fn c_handler(), once per Event Loop it reached */
+pub(crate) fn queued(signal: Signal) {
+    PENDING[index(signal)].fetch_add(1, Ordering::Relaxed);
+}
+
+/* An Event Loop took a delivery off its channel */
+pub(crate) fn dequeued(signal: Signal) {
+    PENDING[index(signal)].fetch_sub(1, Ordering::Relaxed);
+}
+
+/* Wraps a callback so every run records its dispatch latency, how long it
+ * took, and whether it panicked. The panic carries on afterwards, wherever
+ * the callback is running decides what happens to it.
+*/
+pub(crate) fn instrument<F>(signal: Signal, name: &str, callback: F) -> impl Fn(&SignalEvent) + Send
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    let runs = CALLBACKS
+        .lock()
+        .unwrap()
+        .entry((signal as libc::c_int, String::from(name)))
+        .or_default()
+        .clone();
+
+    move |event: &SignalEvent| {
+        let start = Instant::now();
+        LATENCY.lock().unwrap()[index(signal)].record(start.duration_since(event.received()));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| callback(event)));
+
+        let mut runs = runs.lock().unwrap();
+        runs.time.record(start.elapsed());
+        if let Err(panic) = result {
+            runs.panics += 1;
+            drop(runs);
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/*********
+ * STATS *
+ *********/
+
+/// A snapshot of what Addy has counted since the process started. Returned
+/// by `addy::stats()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    signals: Vec<SignalStats>,
+    callbacks: Vec<CallbackStats>,
+}
+
+/// The counters for one signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalStats {
+    signal: Signal,
+    received: u64,
+    coalesced: u64,
+    dropped: u64,
+    pending: u64,
+    latency: Timing,
+}
+
+/// The counters for one named callback. Callbacks with the same name on the
+/// same signal share them, even across Mediators or after being replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallbackStats {
+    signal: Signal,
+    name: String,
+    runs: Timing,
+    panics: u64,
+}
+
+impl SignalStats {
+    /// The signal these are for.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// How many times the handler received the signal.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// How many times the signal arrived while an earlier one was still
+    /// waiting for an Event Loop. See `SignalEvent::coalesced()`.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// How many deliveries couldn't be passed on to an Event Loop.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// How many deliveries are waiting for an Event Loop to get to them.
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    /// Time from the handler receiving the signal to a callback starting,
+    /// counted once per callback run.
+    pub fn latency(&self) -> Timing {
+        self.latency
+    }
+}
+
+impl CallbackStats {
+    /// The signal the callback is registered for.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// The name it was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How many times it ran and how long it took, wherever it ran.
+    pub fn runs(&self) -> Timing {
+        self.runs
+    }
+
+    /// How many of those runs panicked.
+    pub fn panics(&self) -> u64 {
+        self.panics
+    }
+}
+
+/// Takes a snapshot of the counters Addy keeps for each signal and callback.
+///
+/// # Example
+/// ```
+/// use addy::SIGUSR1;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     addy::mediate(SIGUSR1)
+///             .register("count", |_signal| {})?
+///             .enable()?;
+///
+///     let stats = addy::stats();
+///     println!("{} SIGUSR1 so far", stats.signal(SIGUSR1).received());
+///
+///     /* For a metrics endpoint */
+///     print!("{}", stats.to_prometheus());
+///     Ok(())
+/// }
+/// ```
+pub fn stats() -> Stats {
+    let latency = *LATENCY.lock().unwrap();
+    let signals = Signal::iterator()
+        .map(|signal| SignalStats {
+            signal,
+            received: RECEIVED[index(signal)].load(Ordering::Relaxed),
+            coalesced: COALESCED[index(signal)].load(Ordering::Relaxed),
+            dropped: DROPPED[index(signal)].load(Ordering::Relaxed),
+            pending: u64::try_from(PENDING[index(signal)].load(Ordering::Relaxed)).unwrap_or(0),
+            latency: latency[index(signal)],
+        })
+        .collect();
+
+    let callbacks = CALLBACKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|((signum, name), runs)| {
+            let runs = runs.lock().unwrap();
+            Some(CallbackStats {
+                signal: Signal::try_from(*signum).ok()?,
+                name: name.clone(),
+                runs: runs.time,
+                panics: runs.panics,
+            })
+        })
+        .collect();
+
+    Stats { signals, callbacks }
+}
+
+/* Quotes a Prometheus label value */
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Stats {
+    /// The counters for `signal`.
+    pub fn signal(&self, signal: Signal) -> SignalStats {
+        self.signals
+            .iter()
+            .find(|stats| stats.signal == signal)
+            .copied()
+            .unwrap_or(SignalStats {
+                signal,
+                received: 0,
+                coalesced: 0,
+                dropped: 0,
+                pending: 0,
+                latency: Timing::new(),
+            })
+    }
+
+    /// The counters for every signal.
+    pub fn signals(&self) -> &[SignalStats] {
+        &self.signals
+    }
+
+    /// The counters for every callback that has been registered, sorted by
+    /// signal then name.
+    pub fn callbacks(&self) -> &[CallbackStats] {
+        &self.callbacks
+    }
+
+    /// Renders the snapshot in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        /* Writing to a String can't fail */
+        let _ = writeln!(
+            out,
+            "# HELP addy_signals_received_total Signals received by the handler.\n\
+             # TYPE addy_signals_received_total counter"
+        );
+        for stats in &self.signals {
+            let _ = writeln!(
+                out,
+                "addy_signals_received_total{{signal=\"{}\"}} {}",
+                stats.signal, stats.received
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_signals_coalesced_total Signals that arrived while one was pending.\n\
+             # TYPE addy_signals_coalesced_total counter"
+        );
+        for stats in &self.signals {
+            let _ = writeln!(
+                out,
+                "addy_signals_coalesced_total{{signal=\"{}\"}} {}",
+                stats.signal, stats.coalesced
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_signals_dropped_total Deliveries that couldn't reach an Event Loop.\n\
+             # TYPE addy_signals_dropped_total counter"
+        );
+        for stats in &self.signals {
+            let _ = writeln!(
+                out,
+                "addy_signals_dropped_total{{signal=\"{}\"}} {}",
+                stats.signal, stats.dropped
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_signals_pending Deliveries waiting for an Event Loop.\n\
+             # TYPE addy_signals_pending gauge"
+        );
+        for stats in &self.signals {
+            let _ = writeln!(
+                out,
+                "addy_signals_pending{{signal=\"{}\"}} {}",
+                stats.signal, stats.pending
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_dispatch_latency_seconds Time from the handler to a callback starting.\n\
+             # TYPE addy_dispatch_latency_seconds summary"
+        );
+        for stats in &self.signals {
+            let _ = writeln!(
+                out,
+                "addy_dispatch_latency_seconds_sum{{signal=\"{0}\"}} {1}\n\
+                 addy_dispatch_latency_seconds_count{{signal=\"{0}\"}} {2}",
+                stats.signal,
+                stats.latency.total.as_secs_f64(),
+                stats.latency.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_callback_duration_seconds Time spent running a callback.\n\
+             # TYPE addy_callback_duration_seconds summary"
+        );
+        for stats in &self.callbacks {
+            let _ = writeln!(
+                out,
+                "addy_callback_duration_seconds_sum{{signal=\"{0}\",callback=\"{1}\"}} {2}\n\
+                 addy_callback_duration_seconds_count{{signal=\"{0}\",callback=\"{1}\"}} {3}",
+                stats.signal,
+                label(&stats.name),
+                stats.runs.total.as_secs_f64(),
+                stats.runs.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP addy_callback_panics_total Callback runs that panicked.\n\
+             # TYPE addy_callback_panics_total counter"
+        );
+        for stats in &self.callbacks {
+            let _ = writeln!(
+                out,
+                "addy_callback_panics_total{{signal=\"{}\",callback=\"{}\"}} {}",
+                stats.signal,
+                label(&stats.name),
+                stats.panics
+            );
+        }
+
+        out
+    }
+}
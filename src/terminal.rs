@@ -0,0 +1,464 @@
+//! Terminal helpers for TUIs.
+//!
+//! Every SIGWINCH callback ends up calling `ioctl(TIOCGWINSZ)`, so Addy does
+//! it for you. `on_resize(...)` queries the new size on each SIGWINCH,
+//! coalesces bursts (dragging a window corner sends dozens) and only calls
+//! you when the size actually changed.
+//!
+//! `Guard` puts the terminal back the way it found it when the process is
+//! terminated, suspended with Ctrl + Z or stopped for touching the terminal
+//! from the background.
+//!
+//! # Example
+//! ```no_run
+//! use std::io::{Read, stdin};
+//!
+//! fn main() -> Result<(), addy::Error> {
+//!     let size = addy::terminal::current_size()?;
+//!     println!("Starting at {}x{}", size.cols, size.rows);
+//!
+//!     addy::terminal::on_resize(|size| {
+//!         println!("Resized to {}x{}", size.cols, size.rows);
+//!     })?;
+//!
+//!     /* Try resizing your terminal window :) */
+//!     let mut buffer = [0; 1];
+//!     loop {
+//!         let _ = stdin().read(&mut buffer);
+//!     }
+//! }
+//! ```
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Error, Signal, SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP, SIGTTIN, SIGTTOU, SIGWINCH};
+
+/* Every resize listener and guard registers its own callbacks */
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/* How long SIGWINCH has to be quiet before we query the size */
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/*************
+ * TERM SIZE *
+ *************/
+
+/// The size of a terminal as reported by `ioctl(TIOCGWINSZ)`. The pixel
+/// sizes are 0 when the terminal doesn't report them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TermSize {
+    /// Rows of characters.
+    pub rows: u16,
+    /// Columns of characters.
+    pub cols: u16,
+    /// Width in pixels.
+    pub x_pixels: u16,
+    /// Height in pixels.
+    pub y_pixels: u16,
+}
+
+/// Returns the size of the terminal attached to stdout.
+pub fn current_size() -> Result<TermSize, Error> {
+    size_of(libc::STDOUT_FILENO)
+}
+
+/// Returns the size of the terminal attached to `fd`.
+///
+/// # Errors
+/// `Error::Os(ENOTTY)` if `fd` isn't a terminal.
+pub fn size_of(fd: RawFd) -> Result<TermSize, Error> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(TermSize {
+            rows: size.ws_row,
+            cols: size.ws_col,
+            x_pixels: size.ws_xpixel,
+            y_pixels: size.ws_ypixel,
+        }),
+    }
+}
+
+/*************
+ * ON RESIZE *
+ *************/
+
+/// Returned by `on_resize(...)`. Dropping it does _not_ stop the listener,
+/// call `.remove()` for that.
+#[derive(Debug)]
+pub struct ResizeHandle {
+    name: String,
+}
+
+impl ResizeHandle {
+    /// Stops calling the resize callback.
+    pub fn remove(self) -> Result<(), Error> {
+        crate::mediate(SIGWINCH).remove(&self.name)?;
+        Ok(())
+    }
+}
+
+/// Calls `callback` with the new size of the terminal attached to stdout
+/// whenever it changes.
+pub fn on_resize<F>(callback: F) -> Result<ResizeHandle, Error>
+where
+    F: FnMut(TermSize) + Send + 'static,
+{
+    on_resize_fd(libc::STDOUT_FILENO, DEFAULT_DEBOUNCE, callback)
+}
+
+/// Calls `callback` with the new size of the terminal attached to `fd`
+/// whenever it changes. SIGWINCH has to be quiet for `debounce` before the
+/// size is queried, so a burst of signals results in one query.
+///
+/// The callback runs on its own thread, not the Event Loop.
+pub fn on_resize_fd<F>(
+    fd: RawFd,
+    debounce: Duration,
+    mut callback: F,
+) -> Result<ResizeHandle, Error>
+where
+    F: FnMut(TermSize) + Send + 'static,
+{
+    /* Start from the current size so only real changes fire */
+    let mut last = size_of(fd).ok();
+    let (sender, receiver) = mpsc::channel::<()>();
+
+    /* Ends when the SIGWINCH callback (and so the Sender) is dropped */
+    thread::spawn(move || {
+        while receiver.recv().is_ok() {
+            /* Swallow the rest of the burst */
+            loop {
+                match receiver.recv_timeout(debounce) {
+                    Ok(()) => continue,
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            if let Ok(size) = size_of(fd) {
+                if last != Some(size) {
+                    last = Some(size);
+                    callback(size);
+                }
+            }
+        }
+    });
+
+    let name = format!(
+        "addy::terminal::resize::{}",
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    crate::mediate(SIGWINCH)
+        .register(&name, move |_signal| {
+            let _ = sender.send(());
+        })?
+        .enable()?;
+
+    Ok(ResizeHandle { name })
+}
+
+/*********
+ * GUARD *
+ *********/
+
+/* Signals that end the process - restore, then re-raise */
+const TERMINATE: [Signal; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+/* Signals that stop the process - restore, stop, then re-enter */
+const SUSPEND: [Signal; 3] = [SIGTSTP, SIGTTIN, SIGTTOU];
+
+type Hook = Box<dyn FnMut() + Send>;
+
+/* Every signal a Guard registers a callback for */
+fn guarded(terminate: bool) -> Vec<Signal> {
+    let mut signals = SUSPEND.to_vec();
+    signals.push(SIGCONT);
+    if terminate {
+        signals.extend_from_slice(&TERMINATE);
+    }
+    signals
+}
+
+fn get_attr(fd: RawFd) -> Result<libc::termios, Error> {
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    match unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(unsafe { termios.assume_init() }),
+    }
+}
+
+/* A background process changing the terminal gets SIGTTOU, which would stop
+ * us in the middle of restoring it. POSIX lets the change through if
+ * SIGTTOU is blocked, so block it for the duration.
+*/
+fn set_attr(fd: RawFd, termios: &libc::termios) -> Result<(), Error> {
+    unsafe {
+        let mut block = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+        let mut previous = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(block.as_mut_ptr());
+        libc::sigaddset(block.as_mut_ptr(), libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, block.as_ptr(), previous.as_mut_ptr());
+
+        let ret = libc::tcsetattr(fd, libc::TCSADRAIN, termios);
+        let result = match ret {
+            -1 => Err(Error::last_os_error()),
+            _ => Ok(()),
+        };
+
+        libc::pthread_sigmask(libc::SIG_SETMASK, previous.as_ptr(), std::ptr::null_mut());
+        result
+    }
+}
+
+/* Actually stop the process with the signal that asked us to. Goes straight
+ * to the kernel and puts our own handler back afterwards so the Event Loop
+ * never notices.
+*/
+fn stop_with(signal: Signal) {
+    let signum = signal as libc::c_int;
+    unsafe {
+        let mut default = std::mem::zeroed::<libc::sigaction>();
+        let mut previous = std::mem::zeroed::<libc::sigaction>();
+        default.sa_sigaction = libc::SIG_DFL;
+        libc::sigaction(signum, &default, &mut previous);
+
+        let mut unblock = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(unblock.as_mut_ptr());
+        libc::sigaddset(unblock.as_mut_ptr(), signum);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, unblock.as_ptr(), std::ptr::null_mut());
+
+        /* We stop here until someone sends SIGCONT */
+        libc::raise(signum);
+
+        libc::sigaction(signum, &previous, std::ptr::null_mut());
+    }
+}
+
+struct GuardState {
+    fd: RawFd,
+    snapshot: libc::termios,
+    on_suspend: Mutex<Option<Hook>>,
+    on_resume: Mutex<Option<Hook>>,
+    /* Set when the suspend dance already handled the SIGCONT that follows */
+    resumed: AtomicBool,
+}
+
+impl GuardState {
+    fn restore(&self) -> Result<(), Error> {
+        set_attr(self.fd, &self.snapshot)
+    }
+
+    fn terminate(&self, signal: Signal) -> ! {
+        let _ = self.restore();
+        crate::reset_and_raise(signal)
+    }
+
+    fn suspend(&self, signal: Signal) {
+        /* Remember the mode the application had so we can put it back */
+        let current = get_attr(self.fd).ok();
+        if let Some(hook) = self.on_suspend.lock().unwrap().as_mut() {
+            hook();
+        }
+        let _ = self.restore();
+
+        stop_with(signal);
+
+        if let Some(current) = current {
+            let _ = set_attr(self.fd, &current);
+        }
+        self.resumed.store(true, Ordering::SeqCst);
+        self.resume_hook();
+    }
+
+    fn cont(&self) {
+        /* Continued after a stop we didn't see (e.g. SIGSTOP) */
+        if !self.resumed.swap(false, Ordering::SeqCst) {
+            self.resume_hook();
+        }
+    }
+
+    fn resume_hook(&self) {
+        if let Some(hook) = self.on_resume.lock().unwrap().as_mut() {
+            hook();
+        }
+    }
+}
+
+/// Snapshots the terminal settings (termios) of an fd and restores them
+/// whenever the process leaves the terminal.
+///
+/// * On SIGINT, SIGTERM and SIGHUP the snapshot is restored and the signal
+///   re-raised with its default disposition.
+/// * On SIGTSTP (Ctrl + Z), SIGTTIN and SIGTTOU the snapshot is restored, the
+///   suspend hook runs and the process actually stops. When it's continued
+///   the terminal settings it had are put back and the resume hook runs.
+/// * On SIGCONT after a stop Addy didn't see (e.g. SIGSTOP) the resume hook
+///   runs so you can redraw.
+/// * Dropping the Guard restores the snapshot, removes the callbacks and
+///   puts back the default behaviour of the signals nothing else was
+///   handling when it was installed.
+///
+/// # Example
+/// ```no_run
+/// fn main() -> Result<(), addy::Error> {
+///     let guard = addy::terminal::Guard::builder(libc::STDIN_FILENO)
+///             .on_suspend(|| { /* Leave the alternate screen */ })
+///             .on_resume(|| { /* Enter the alternate screen and redraw */ })
+///             .install()?;
+///
+///     /* Put the terminal in raw mode, run the TUI... */
+///
+///     drop(guard);
+///     Ok(())
+/// }
+/// ```
+pub struct Guard {
+    name: String,
+    terminate: bool,
+    /* Signals nothing handled before the Guard, put back to default on drop */
+    enabled: Vec<Signal>,
+    state: Arc<GuardState>,
+}
+
+impl std::fmt::Debug for Guard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Guard")
+            .field("name", &self.name)
+            .field("fd", &self.state.fd)
+            .field("terminate", &self.terminate)
+            .finish()
+    }
+}
+
+impl Guard {
+    /// Returns a builder that will guard the terminal attached to `fd`.
+    pub fn builder(fd: RawFd) -> GuardBuilder {
+        GuardBuilder {
+            fd,
+            terminate: true,
+            on_suspend: None,
+            on_resume: None,
+        }
+    }
+
+    /// Guards the terminal attached to `fd` with no hooks.
+    pub fn new(fd: RawFd) -> Result<Guard, Error> {
+        Guard::builder(fd).install()
+    }
+
+    /// Restores the snapshot now without giving up the Guard.
+    pub fn restore(&self) -> Result<(), Error> {
+        self.state.restore()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = self.state.restore();
+        for signal in guarded(self.terminate) {
+            let mut handle = crate::mediate(signal);
+            let _ = handle.remove(&self.name);
+            if self.enabled.contains(&signal) {
+                let _ = handle.default();
+            }
+        }
+    }
+}
+
+/// Configures a `Guard`. Created with `addy::terminal::Guard::builder(fd)`.
+pub struct GuardBuilder {
+    fd: RawFd,
+    terminate: bool,
+    on_suspend: Option<Hook>,
+    on_resume: Option<Hook>,
+}
+
+impl std::fmt::Debug for GuardBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GuardBuilder")
+            .field("fd", &self.fd)
+            .field("terminate", &self.terminate)
+            .finish()
+    }
+}
+
+impl GuardBuilder {
+    /// Runs before the terminal is restored and the process stops.
+    pub fn on_suspend<F: FnMut() + Send + 'static>(mut self, hook: F) -> Self {
+        self.on_suspend = Some(Box::new(hook));
+        self
+    }
+
+    /// Runs after the process is continued and the terminal re-entered.
+    pub fn on_resume<F: FnMut() + Send + 'static>(mut self, hook: F) -> Self {
+        self.on_resume = Some(Box::new(hook));
+        self
+    }
+
+    /// Whether to restore and re-raise on SIGINT, SIGTERM and SIGHUP
+    /// (default true). Turn it off if something else, like `addy::Shutdown`,
+    /// owns those signals and call `Guard::restore()` from there instead.
+    pub fn restore_on_terminate(mut self, terminate: bool) -> Self {
+        self.terminate = terminate;
+        self
+    }
+
+    /// Snapshots the terminal settings and registers the callbacks.
+    ///
+    /// # Errors
+    /// `Error::Os(ENOTTY)` if the fd isn't a terminal.
+    pub fn install(self) -> Result<Guard, Error> {
+        let state = Arc::new(GuardState {
+            fd: self.fd,
+            snapshot: get_attr(self.fd)?,
+            on_suspend: Mutex::new(self.on_suspend),
+            on_resume: Mutex::new(self.on_resume),
+            resumed: AtomicBool::new(false),
+        });
+        let name = format!(
+            "addy::terminal::guard::{}",
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        /* Whatever was already handling a signal keeps it after the Guard */
+        let enabled = guarded(self.terminate)
+            .into_iter()
+            .filter(|signal| !crate::is_handled(*signal))
+            .collect();
+
+        if self.terminate {
+            for signal in TERMINATE.iter() {
+                let state = Arc::clone(&state);
+                crate::mediate(*signal)
+                    .register(&name, move |signal| state.terminate(signal))?
+                    .enable()?;
+            }
+        }
+        for signal in SUSPEND.iter() {
+            let state = Arc::clone(&state);
+            crate::mediate(*signal)
+                .register(&name, move |signal| state.suspend(signal))?
+                .enable()?;
+        }
+        let cont = Arc::clone(&state);
+        crate::mediate(SIGCONT)
+            .register(&name, move |_signal| cont.cont())?
+            .enable()?;
+
+        Ok(Guard {
+            name,
+            terminate: self.terminate,
+            enabled,
+            state,
+        })
+    }
+}
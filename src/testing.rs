@@ -0,0 +1,210 @@
+//! Runs callbacks for made up signals, without going through the kernel.
+//!
+//! Raising SIGTERM or SIGINT at the test process is flaky under `cargo test`
+//! and takes the whole test binary down if the handler isn't installed yet.
+//! Instead, `inject(...)` hands the Event Loop a `SignalEvent` as if
+//! `fn c_handler()` had just sent it, waits for the callbacks to finish, and
+//! returns the names of the callbacks that ran, in the order they ran.
+//!
+//! For tests that need the real thing, e.g. checking the process actually
+//! dies once a handler is released, `in_subprocess(...)` runs a closure in a
+//! fresh copy of the test binary so dispositions never leak between tests.
+//!
+//! Only built with the `testing` feature.
+//!
+//! # Example
+//! ```
+//! use addy::{SignalEvent, SIGTERM};
+//!
+//! fn main() -> Result<(), addy::Error> {
+//!     addy::mediate(SIGTERM)
+//!             .register("flush", |_signal| { /* Flush the logs */ })?;
+//!
+//!     /* No need to .enable(), nothing is ever sent to the process */
+//!     let ran = addy::testing::inject(SignalEvent::new(SIGTERM))?;
+//!     assert_eq!(ran, vec!["flush"]);
+//!     Ok(())
+//! }
+//! ```
+use std::convert::TryFrom;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::sync::mpsc;
+use std::{env, panic, process, thread};
+
+use crate::{Action, Error, Mediator, Signal, SignalEvent};
+
+/* Set in the child to the name of the test it was started for */
+const CHILD_ENV: &str = "ADDY_TESTING_SUBPROCESS";
+
+/* Written to stdout and stderr by the child right before it runs the
+ * closure. Anything before it is the test harness talking, not the closure.
+*/
+const MARKER: &str = "--addy-testing-subprocess--\n";
+
+/*************
+ * INJECTION *
+ *************/
+
+/// Runs the default Mediator's callbacks for `event`'s signal and returns
+/// the names of the ones that ran, in the order they ran. Callbacks
+/// registered with an `Execution` other than `Execution::Inline` have only
+/// been handed off when this returns, not necessarily finished.
+///
+/// The callbacks run whether or not the signal is enabled, and nothing
+/// about the signal's disposition changes. Blocks until the Event Loop gets
+/// to it, so with a `Dispatcher` it has to be polled from another thread.
+pub fn inject(event: SignalEvent) -> Result<Vec<String>, Error> {
+    inject_into(&Mediator::global(), event)
+}
+
+/// Like `inject(...)`, but for the callbacks of `mediator` instead of the
+/// default Mediator.
+///
+/// # Example
+/// ```
+/// use addy::{Mediator, SignalEvent, SIGINT};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let mediator = Mediator::new()?;
+///     mediator.mediate(SIGINT)
+///             .register_event("who", |event| assert_eq!(event.pid(), Some(42)))?;
+///
+///     let event = SignalEvent::new(SIGINT).with_sender(42, 0);
+///     assert_eq!(addy::testing::inject_into(&mediator, event)?, vec!["who"]);
+///
+///     /* Other Mediators never see injected events */
+///     assert!(addy::testing::inject(SignalEvent::new(SIGINT))?.is_empty());
+///     Ok(())
+/// }
+/// ```
+pub fn inject_into(mediator: &Mediator, event: SignalEvent) -> Result<Vec<String>, Error> {
+    let handle = mediator.mediate(event.signal());
+    let (reply, ran) = mpsc::channel();
+    handle
+        .sender
+        .send(Action::Inject(event, reply))
+        .map_err(|_| Error::CallFailed)?;
+    ran.recv().map_err(|_| Error::CallFailed)
+}
+
+/*****************
+ * IN SUBPROCESS *
+ *****************/
+
+/// How the closure passed to `in_subprocess(...)` ended, and what it wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subprocess {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Subprocess {
+    /// The child's exit status. Exits with 0 if the closure returned and 101
+    /// if it panicked, like a test would.
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// True if the closure returned without panicking.
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// The signal that terminated the child, if a signal did. None for
+    /// signals Addy doesn't know about (e.g. real-time signals).
+    pub fn signal(&self) -> Option<Signal> {
+        self.status
+            .signal()
+            .and_then(|signum| Signal::try_from(signum).ok())
+    }
+
+    /// Everything the closure wrote to stdout.
+    pub fn stdout(&self) -> &[u8] {
+        &self.stdout
+    }
+
+    /// Everything the closure wrote to stderr, including the panic message
+    /// if it panicked.
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+}
+
+/* Drops whatever the test harness wrote before the child's marker */
+fn after_marker(output: Vec<u8>) -> Vec<u8> {
+    let marker = MARKER.as_bytes();
+    match output
+        .windows(marker.len())
+        .position(|window| window == marker)
+    {
+        Some(start) => output[start + marker.len()..].to_vec(),
+        None => output,
+    }
+}
+
+/// Runs `f` in a child process and returns how it ended, with its stdout
+/// and stderr. Signals raised, handlers installed and dispositions changed
+/// by `f` stay in the child.
+///
+/// The child is the current executable started again, running only the
+/// current test, where `in_subprocess(...)` runs `f` and exits instead of
+/// returning. Because of that it has to be called from a `#[test]` run by
+/// the standard test harness (or straight from a doc test's `main`), at
+/// most once per test, and anything the test does before the call happens
+/// in the child too.
+///
+/// # Example
+/// ```
+/// use addy::SIGTERM;
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let child = addy::testing::in_subprocess(|| {
+///         addy::mediate(SIGTERM)
+///                 .register("print", |_signal| println!("Ignoring it"))
+///                 .unwrap()
+///                 .enable()
+///                 .unwrap()
+///                 .release()
+///                 .unwrap();
+///
+///         /* Give the Event Loop a moment to put the default back */
+///         std::thread::sleep(std::time::Duration::from_millis(100));
+///         SIGTERM.raise().unwrap();
+///         std::thread::sleep(std::time::Duration::from_secs(5));
+///     })?;
+///
+///     assert_eq!(child.signal(), Some(SIGTERM));
+///     assert!(child.stdout().is_empty());
+///     Ok(())
+/// }
+/// ```
+pub fn in_subprocess<F: FnOnce()>(f: F) -> Result<Subprocess, Error> {
+    /* libtest runs each test on a thread named after it */
+    let name = String::from(thread::current().name().unwrap_or("main"));
+
+    /* We're the child, run the closure and never return to the test */
+    if env::var(CHILD_ENV).ok().as_deref() == Some(name.as_str()) {
+        let _ = std::io::stdout().write_all(MARKER.as_bytes());
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().write_all(MARKER.as_bytes());
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+        let _ = std::io::stdout().flush();
+        process::exit(if result.is_ok() { 0 } else { 101 });
+    }
+
+    let exe = env::current_exe().map_err(|err| Error::Os(err.raw_os_error().unwrap_or(0)))?;
+    let output = Command::new(exe)
+        .args([name.as_str(), "--exact", "--nocapture", "--test-threads=1"])
+        .env(CHILD_ENV, &name)
+        .output()
+        .map_err(|err| Error::Os(err.raw_os_error().unwrap_or(0)))?;
+
+    Ok(Subprocess {
+        status: output.status,
+        stdout: after_marker(output.stdout),
+        stderr: after_marker(output.stderr),
+    })
+}
@@ -0,0 +1,514 @@
+//! Timers that deliver to callbacks on the Event Loop.
+//!
+//! `alarm(...)` and `interval(...)` arm the process wide interval timers with
+//! `setitimer(...)`, which raise SIGALRM, SIGVTALRM or SIGPROF depending on
+//! the `Clock`. There is only one timer per clock, so arming one replaces
+//! whatever was armed on that clock before. Once a one-shot timer has fired
+//! or a timer is cancelled the clock's signal goes back to its default
+//! behaviour, unless other callbacks are registered for it.
+//!
+//! On Linux `create(...)` makes POSIX timers with `timer_create(...)` instead.
+//! They all share the first real-time signal (`SIGRTMIN`), carry their id in
+//! `si_value` and any number of them can run at once. `SIGRTMIN` has no
+//! `Signal`, so their panics, stats and traces are filed under SIGALRM.
+//!
+//! Either way the callbacks run on the Event Loop thread like any other
+//! callback registered with Addy.
+//!
+//! # Example
+//! ```
+//! use std::sync::mpsc;
+//! use std::time::Duration;
+//! use addy::timer::{self, Clock};
+//!
+//! fn main() -> Result<(), addy::Error> {
+//!     let (tx, rx) = mpsc::channel();
+//!     timer::alarm(Clock::Real, Duration::from_millis(20), move || {
+//!         let _ = tx.send("ding");
+//!     })?;
+//!
+//!     assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("ding"));
+//!     Ok(())
+//! }
+//! ```
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{Error, Signal, SIGALRM, SIGPROF, SIGVTALRM};
+
+/* Ids start at 1 so 0 can mean "nothing armed" */
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/* Id of the timer currently armed on each Clock, 0 if none */
+static ARMED: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/*********
+ * CLOCK *
+ *********/
+
+/// Which of the process' interval timers to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Clock {
+    /// Wall clock time (`ITIMER_REAL`), delivers SIGALRM.
+    Real,
+    /// CPU time spent in user mode (`ITIMER_VIRTUAL`), delivers SIGVTALRM.
+    Virtual,
+    /// CPU time spent in user and kernel mode (`ITIMER_PROF`), delivers
+    /// SIGPROF.
+    Prof,
+}
+
+impl Clock {
+    /// The signal the timer delivers when it expires.
+    pub fn signal(self) -> Signal {
+        match self {
+            Clock::Real => SIGALRM,
+            Clock::Virtual => SIGVTALRM,
+            Clock::Prof => SIGPROF,
+        }
+    }
+
+    fn which(self) -> libc::c_int {
+        match self {
+            Clock::Real => libc::ITIMER_REAL,
+            Clock::Virtual => libc::ITIMER_VIRTUAL,
+            Clock::Prof => libc::ITIMER_PROF,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn callback_name(self, id: usize) -> String {
+        let clock = match self {
+            Clock::Real => "real",
+            Clock::Virtual => "virtual",
+            Clock::Prof => "prof",
+        };
+        format!("addy::timer::{}::{}", clock, id)
+    }
+}
+
+/****************
+ * TIMER HANDLE *
+ ****************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Interval(Clock, usize),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Posix(usize),
+}
+
+/// Returned when a timer is armed. Dropping it leaves the timer running,
+/// call `.cancel()` to stop it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle {
+    kind: Kind,
+}
+
+impl TimerHandle {
+    /// Disarms the timer and drops its callback. Does nothing if the timer
+    /// already expired (one-shot) or was replaced by a newer timer on the
+    /// same `Clock`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use addy::timer::{self, Clock};
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     let timer = timer::interval(Clock::Real, Duration::from_secs(1), || {
+    ///         println!("tick");
+    ///     })?;
+    ///     timer.cancel()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn cancel(self) -> Result<(), Error> {
+        match self.kind {
+            Kind::Interval(clock, id) => {
+                let armed = &ARMED[clock.index()];
+                if armed
+                    .compare_exchange(id, 0, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+                {
+                    return Ok(());
+                }
+                /* Behind the job that armed it on the Event Loop */
+                crate::run_on_loop(move || {
+                    let _ = set_itimer(clock, Duration::from_secs(0), Duration::from_secs(0));
+                })?;
+                crate::mediate(clock.signal()).retire(clock.callback_name(id))?;
+                Ok(())
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Kind::Posix(id) => {
+                posix::cancel(id);
+                Ok(())
+            }
+        }
+    }
+}
+
+/******************
+ * INTERVAL TIMER *
+ ******************/
+
+fn timeval(duration: Duration) -> libc::timeval {
+    libc::timeval {
+        tv_sec: duration.as_secs() as libc::time_t,
+        tv_usec: duration.subsec_micros() as libc::suseconds_t,
+    }
+}
+
+fn set_itimer(clock: Clock, value: Duration, interval: Duration) -> Result<(), Error> {
+    let timer = libc::itimerval {
+        it_interval: timeval(interval),
+        it_value: timeval(value),
+    };
+    match unsafe { libc::setitimer(clock.which(), &timer, std::ptr::null_mut()) } {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/* An interval of zero makes a one-shot timer */
+fn arm<F>(
+    clock: Clock,
+    value: Duration,
+    interval: Duration,
+    callback: F,
+) -> Result<TimerHandle, Error>
+where
+    F: FnMut() + Send + 'static,
+{
+    if value == Duration::from_secs(0) {
+        return Err(Error::InvalidArgument);
+    }
+    /* setitimer(...) only goes down to microseconds and zero disarms it */
+    let value = value.max(Duration::from_micros(1));
+    let once = interval == Duration::from_secs(0);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let name = clock.callback_name(id);
+    let callback = Mutex::new(callback);
+    crate::mediate(clock.signal())
+        .register(clock.callback_name(id), move |signal| {
+            let armed = &ARMED[clock.index()];
+            if once {
+                /* Cancelled or replaced while the signal was in flight */
+                if armed
+                    .compare_exchange(id, 0, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+                {
+                    return;
+                }
+                /* Nothing armed on the clock now, stop catching its signal */
+                let _ = crate::mediate(signal).retire(&name);
+            } else if armed.load(Ordering::SeqCst) != id {
+                return;
+            }
+            /* An earlier panic was already reported, keep ticking */
+            (callback.lock().unwrap_or_else(|err| err.into_inner()))();
+        })?
+        .enable()?;
+
+    /* The timer this one replaces won't fire again, drop its callback */
+    let previous = ARMED[clock.index()].swap(id, Ordering::SeqCst);
+    if previous != 0 {
+        crate::mediate(clock.signal()).remove(clock.callback_name(previous))?;
+    }
+
+    /* Arm it from the Event Loop so the handler is definitely installed by
+     * the time the signal arrives. It would kill us otherwise. It can only
+     * fail for out of range values, which a Duration can't produce.
+    	*/
+    crate::run_on_loop(move || {
+        let _ = set_itimer(clock, value, interval);
+    })?;
+
+    Ok(TimerHandle {
+        kind: Kind::Interval(clock, id),
+    })
+}
+
+/// Calls `callback` once, `after` has elapsed on `clock`. Replaces any timer
+/// already armed on that clock. Returns `Error::InvalidArgument` for a zero
+/// duration.
+pub fn alarm<F>(clock: Clock, after: Duration, callback: F) -> Result<TimerHandle, Error>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut callback = Some(callback);
+    arm(clock, after, Duration::from_secs(0), move || {
+        if let Some(callback) = callback.take() {
+            callback();
+        }
+    })
+}
+
+/// Calls `callback` every time `every` elapses on `clock`, until cancelled.
+/// Replaces any timer already armed on that clock. Returns
+/// `Error::InvalidArgument` for a zero duration.
+///
+/// # Example
+/// ```
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+/// use addy::timer::{self, Clock};
+///
+/// fn main() -> Result<(), addy::Error> {
+///     let (tx, rx) = mpsc::channel();
+///     let timer = timer::interval(Clock::Real, Duration::from_millis(10), move || {
+///         let _ = tx.send(());
+///     })?;
+///
+///     for _ in 0..3 {
+///         rx.recv_timeout(Duration::from_secs(5)).unwrap();
+///     }
+///     timer.cancel()?;
+///     Ok(())
+/// }
+/// ```
+pub fn interval<F>(clock: Clock, every: Duration, callback: F) -> Result<TimerHandle, Error>
+where
+    F: FnMut() + Send + 'static,
+{
+    if every == Duration::from_secs(0) {
+        return Err(Error::InvalidArgument);
+    }
+    arm(clock, every, every.max(Duration::from_micros(1)), callback)
+}
+
+/***************
+ * POSIX TIMER *
+ ***************/
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use posix::create;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use posix::dispatch;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod posix {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use fnv::FnvHashMap;
+    use lazy_static::lazy_static;
+
+    use super::{Kind, TimerHandle, NEXT_ID};
+    use crate::{Action, Error, SignalEvent, SIGALRM};
+
+    type Callback = Arc<dyn Fn(&SignalEvent) + Send + Sync>;
+
+    struct Timer {
+        /* The kernel's timer_t, kept as an integer so the map is Send */
+        timer: usize,
+        once: bool,
+        callback: Callback,
+    }
+
+    struct Registry {
+        /* Whether fn timer_handler() has been installed for SIGRTMIN */
+        installed: bool,
+        timers: FnvHashMap<usize, Timer>,
+    }
+
+    lazy_static! {
+        static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+            installed: false,
+            timers: FnvHashMap::default(),
+        });
+    }
+
+    /* Every POSIX timer lands here. Forwards the id it carries to the Event
+     * Loop, which looks up the callback.
+    	*/
+    extern "C" fn timer_handler(
+        _signum: libc::c_int,
+        info: *mut libc::siginfo_t,
+        _: *mut libc::c_void,
+    ) {
+        if info.is_null() {
+            return;
+        }
+        let id = unsafe {
+            if (*info).si_code != libc::SI_TIMER {
+                return;
+            }
+            (*info).si_value().sival_ptr as usize
+        };
+        crate::kernel::send_default(Action::Timer(id));
+    }
+
+    /* The real-time signals aren't in the Signal enum so the Event Loop's
+     * handler can't be used, ours goes straight to sigaction(...).
+    	*/
+    fn install_handler() -> Result<(), Error> {
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+            timer_handler;
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            if crate::altstack::enabled() {
+                action.sa_flags |= libc::SA_ONSTACK;
+            }
+            libc::sigfillset(&mut action.sa_mask);
+            match libc::sigaction(libc::SIGRTMIN(), &action, std::ptr::null_mut()) {
+                -1 => Err(Error::last_os_error()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn timespec(duration: Duration) -> libc::timespec {
+        /* Some targets have padding fields */
+        let mut spec = unsafe { std::mem::zeroed::<libc::timespec>() };
+        spec.tv_sec = duration.as_secs() as libc::time_t;
+        spec.tv_nsec = duration.subsec_nanos() as libc::c_long;
+        spec
+    }
+
+    fn delete(timer: usize) {
+        unsafe {
+            libc::timer_delete(timer as libc::timer_t);
+        }
+    }
+
+    /// Creates a POSIX timer on `CLOCK_MONOTONIC` that calls `callback` once
+    /// `after` has elapsed, then every `every` if given. Any number of these
+    /// can be running at once, they don't interfere with `alarm(...)` or
+    /// `interval(...)`.
+    ///
+    /// Returns `Error::InvalidArgument` for zero durations.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// fn main() -> Result<(), addy::Error> {
+    ///     let (tx, rx) = mpsc::channel();
+    ///     for n in 1..=3 {
+    ///         let tx = tx.clone();
+    ///         addy::timer::create(Duration::from_millis(10 * n), None, move || {
+    ///             let _ = tx.send(n);
+    ///         })?;
+    ///     }
+    ///
+    ///     let mut fired: Vec<u64> = rx.iter().take(3).collect();
+    ///     fired.sort();
+    ///     assert_eq!(fired, vec![1, 2, 3]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn create<F>(
+        after: Duration,
+        every: Option<Duration>,
+        callback: F,
+    ) -> Result<TimerHandle, Error>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        if after == Duration::from_secs(0) || every == Some(Duration::from_secs(0)) {
+            return Err(Error::InvalidArgument);
+        }
+
+        /* The handler forwards to the Event Loop, it has to exist first */
+        crate::setup();
+
+        let mut registry = REGISTRY.lock().unwrap();
+        if !registry.installed {
+            install_handler()?;
+            registry.installed = true;
+        }
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let timer = unsafe {
+            let mut event = std::mem::zeroed::<libc::sigevent>();
+            event.sigev_notify = libc::SIGEV_SIGNAL;
+            event.sigev_signo = libc::SIGRTMIN();
+            event.sigev_value.sival_ptr = id as *mut libc::c_void;
+
+            let mut timer = std::mem::zeroed::<libc::timer_t>();
+            if libc::timer_create(libc::CLOCK_MONOTONIC, &mut event, &mut timer) == -1 {
+                return Err(Error::last_os_error());
+            }
+            timer as usize
+        };
+
+        /* Counted, traced and reported like a callback registered for
+         * SIGALRM. An earlier panic was already reported, keep ticking.
+        	*/
+        let callback = Mutex::new(callback);
+        let callback = crate::observe(SIGALRM, &format!("addy::timer::posix::{}", id), move |_| {
+            (callback.lock().unwrap_or_else(|err| err.into_inner()))()
+        });
+
+        /* In the map before it's armed so the first expiry finds it */
+        registry.timers.insert(
+            id,
+            Timer {
+                timer,
+                once: every.is_none(),
+                callback: Arc::new(callback),
+            },
+        );
+
+        let spec = libc::itimerspec {
+            it_interval: timespec(every.unwrap_or_default()),
+            it_value: timespec(after),
+        };
+        let armed =
+            unsafe { libc::timer_settime(timer as libc::timer_t, 0, &spec, std::ptr::null_mut()) };
+        if armed == -1 {
+            let err = Error::last_os_error();
+            registry.timers.remove(&id);
+            delete(timer);
+            return Err(err);
+        }
+
+        Ok(TimerHandle {
+            kind: Kind::Posix(id),
+        })
+    }
+
+    /* Called by the Event Loop when timer <id> fires. The callback runs with
+     * the lock released so it can create and cancel timers. A panic is
+     * reported before it leaves here, the Event Loop catches it.
+    	*/
+    pub(crate) fn dispatch(id: usize) {
+        let callback = {
+            let mut registry = REGISTRY.lock().unwrap();
+            match registry.timers.get(&id) {
+                /* Cancelled while the signal was in flight */
+                None => return,
+                Some(timer) if timer.once => {
+                    let timer = registry.timers.remove(&id).unwrap();
+                    delete(timer.timer);
+                    timer.callback
+                }
+                Some(timer) => timer.callback.clone(),
+            }
+        };
+        callback(&SignalEvent::new(SIGALRM));
+    }
+
+    pub(super) fn cancel(id: usize) {
+        let timer = REGISTRY.lock().unwrap().timers.remove(&id);
+        if let Some(timer) = timer {
+            delete(timer.timer);
+        }
+    }
+}
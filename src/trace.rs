@@ -0,0 +1,140 @@
+/* Logging for the Event Loop.
+ *
+ * With the `log` and/or `tracing` features every Action the Event Loop
+ * processes becomes an event under the "addy" target, and every callback
+ * run gets its own span. Without either feature these compile to nothing.
+*/
+#![cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+
+use std::time::Duration;
+#[cfg(any(feature = "log", feature = "tracing"))]
+use std::time::Instant;
+
+use crate::{Action, Signal, SignalEvent};
+
+/* The name, signal and callback name to report for an Action. Call is
+ * reported by fn called() once its callbacks have run, the rest aren't
+ * about callbacks.
+*/
+fn describe(action: &Action) -> Option<(&'static str, Option<Signal>, Option<String>)> {
+    match action {
+        Action::Register(registration) => Some((
+            "register",
+            Some(registration.signal),
+            Some(registration.name.to_string()),
+        )),
+        Action::Remove(signal, name) => Some(("remove", Some(*signal), Some(name.to_string()))),
+        Action::Retire(signal, name) => Some(("retire", Some(*signal), Some(name.to_string()))),
+        Action::RemoveTag(tag) => Some(("remove_tag", None, Some(format!("#{}", tag)))),
+        Action::ClearNamespace(namespace) => {
+            Some(("clear_namespace", None, Some(format!("{}::*", namespace))))
+        }
+        Action::Clear(signal) => Some(("clear", Some(*signal), None)),
+        Action::Ignore(signal) => Some(("ignore", Some(*signal), None)),
+        Action::Default(signal) => Some(("default", Some(*signal), None)),
+        Action::Release(signal) => Some(("release", Some(*signal), None)),
+        Action::Resume(signal) => Some(("resume", Some(*signal), None)),
+        Action::Options(signal, _) => Some(("options", Some(*signal), None)),
+        _ => None,
+    }
+}
+
+/* The Event Loop is about to process action */
+pub(crate) fn action(action: &Action) {
+    let (kind, signal, name) = match describe(action) {
+        Some(described) => described,
+        None => return,
+    };
+
+    #[cfg(feature = "log")]
+    log::debug!(
+        target: "addy",
+        "{} {} callback={}",
+        kind,
+        signal.map_or("-", Signal::as_str),
+        name.as_deref().unwrap_or("-")
+    );
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: "addy",
+        action = kind,
+        signal = signal.map(Signal::as_str),
+        callback = name.as_deref(),
+    );
+}
+
+/* The Event Loop ran every callback for event. Once per signal, so it's
+ * kept below info.
+*/
+pub(crate) fn called(event: &SignalEvent, took: Duration) {
+    #[cfg(feature = "log")]
+    log::debug!(
+        target: "addy",
+        "call {} pid={} duration={:?}",
+        event.signal(),
+        event.pid().map_or(String::from("-"), |pid| pid.to_string()),
+        took
+    );
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: "addy",
+        action = "call",
+        signal = event.signal().as_str(),
+        pid = event.pid(),
+        duration = ?took,
+    );
+}
+
+/* Wraps a callback so every run happens inside a span and reports how long
+ * it took, wherever it ends up running.
+*/
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub(crate) fn instrument<F>(signal: Signal, name: &str, callback: F) -> impl Fn(&SignalEvent) + Send
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    let name = String::from(name);
+    move |event: &SignalEvent| {
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!(
+            target: "addy",
+            "callback",
+            signal = signal.as_str(),
+            callback = name.as_str(),
+            pid = event.pid(),
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        callback(event);
+        let took = start.elapsed();
+
+        #[cfg(feature = "log")]
+        log::trace!(
+            target: "addy",
+            "callback {} {} pid={} duration={:?}",
+            signal,
+            name,
+            event.pid().map_or(String::from("-"), |pid| pid.to_string()),
+            took
+        );
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "addy", duration = ?took, "callback finished");
+    }
+}
+
+/* Nothing to report to, leave the callback alone */
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+pub(crate) fn instrument<F>(_signal: Signal, _name: &str, callback: F) -> F
+where
+    F: Fn(&SignalEvent) + Send + 'static,
+{
+    callback
+}